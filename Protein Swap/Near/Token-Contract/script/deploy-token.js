@@ -18,7 +18,10 @@ const CONFIG = {
     wasmPath: process.env.WASM_PATH || './target/near/v1tokens.wasm',
     totalSupply: process.env.TOTAL_SUPPLY || '1000000000000000000000000', // 1 million tokens with 24 decimals
     gasLimit: process.env.GAS_LIMIT || '300000000000000',
-    attachedDeposit: process.env.ATTACHED_DEPOSIT || '0'
+    attachedDeposit: process.env.ATTACHED_DEPOSIT || '0',
+    maxSupply: process.env.MAX_SUPPLY || null,
+    faucetMode: process.env.FAUCET_MODE === 'true',
+    complianceEnabled: process.env.COMPLIANCE_ENABLED === 'true'
 };
 
 // Validate required environment variables
@@ -105,7 +108,10 @@ async function deployContract() {
             methodName: 'new',
             args: {
                 owner_id: CONFIG.ownerAccountId,
-                total_supply: CONFIG.totalSupply
+                total_supply: CONFIG.totalSupply,
+                max_supply: CONFIG.maxSupply,
+                faucet_mode: CONFIG.faucetMode,
+                compliance_enabled: CONFIG.complianceEnabled
             },
             gas: CONFIG.gasLimit,
             attachedDeposit: CONFIG.attachedDeposit
@@ -182,6 +188,9 @@ function displayConfig() {
     console.log(`   Total Supply: ${CONFIG.totalSupply}`);
     console.log(`   Gas Limit: ${CONFIG.gasLimit}`);
     console.log(`   Attached Deposit: ${CONFIG.attachedDeposit}`);
+    console.log(`   Max Supply: ${CONFIG.maxSupply || 'unlimited'}`);
+    console.log(`   Faucet Mode: ${CONFIG.faucetMode}`);
+    console.log(`   Compliance Enabled: ${CONFIG.complianceEnabled}`);
     console.log('');
 }
 
@@ -73,6 +73,33 @@ async function initNear() {
     }
 }
 
+// Register an account with the token contract (NEP-145) if it isn't already
+async function ensureRegistered(account, accountId) {
+    const storageBalance = await account.viewFunction({
+        contractId: CONFIG.tokenAccountId,
+        methodName: 'storage_balance_of',
+        args: { account_id: accountId }
+    });
+    if (storageBalance) {
+        return;
+    }
+
+    const bounds = await account.viewFunction({
+        contractId: CONFIG.tokenAccountId,
+        methodName: 'storage_balance_bounds',
+        args: {}
+    });
+
+    console.log(`📝 Registering ${accountId} with the token contract (${bounds.min} yoctoNEAR)...`);
+    await account.functionCall({
+        contractId: CONFIG.tokenAccountId,
+        methodName: 'storage_deposit',
+        args: { account_id: accountId, registration_only: true },
+        gas: CONFIG.gasLimit,
+        attachedDeposit: bounds.min
+    });
+}
+
 // Mint tokens
 async function mintTokens(amount = process.env.MINT_AMOUNT || '100000000000000000000000') {
     try {
@@ -83,6 +110,7 @@ async function mintTokens(amount = process.env.MINT_AMOUNT || '10000000000000000
         
         const near = await initNear();
         const account = await near.account(CONFIG.minterAccountId);
+        await ensureRegistered(account, CONFIG.recipientAccountId);
         
         const result = await account.functionCall({
             contractId: CONFIG.tokenAccountId,
@@ -123,6 +151,7 @@ async function transferTokens(recipient, amount = process.env.TRANSFER_AMOUNT ||
         
         const near = await initNear();
         const account = await near.account(CONFIG.minterAccountId);
+        await ensureRegistered(account, recipient);
         
         const result = await account.functionCall({
             contractId: CONFIG.tokenAccountId,
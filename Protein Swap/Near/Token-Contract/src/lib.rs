@@ -1,18 +1,31 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::LookupMap;
-use near_sdk::json_types::U128;
+use near_sdk::collections::{LookupMap, LookupSet, UnorderedSet, Vector};
+use near_sdk::json_types::{Base64VecU8, U128};
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::{
-    env, near_bindgen, require, AccountId, Gas, NearToken, PanicOnDefault,
+    env, log, near_bindgen, require, AccountId, Gas, NearToken, PanicOnDefault, Promise,
     PromiseOrValue, PromiseResult,
 };
 
 // Type alias for Balance
 type Balance = u128;
 
-// Gas constants for cross-contract calls
-const GAS_FOR_FT_TRANSFER_CALL: Gas = Gas::from_tgas(25);
+// Gas constants for cross-contract calls. `ft_transfer_call` keeps GAS_FOR_FT_TRANSFER_CALL
+// to finish itself, reserves GAS_FOR_RESOLVE_TRANSFER for the callback and forwards the rest
+// of the prepaid gas (at least MIN_GAS_FOR_RECEIVER) to the receiver.
+const GAS_FOR_FT_TRANSFER_CALL: Gas = Gas::from_tgas(10);
 const GAS_FOR_RESOLVE_TRANSFER: Gas = Gas::from_tgas(10);
+const MIN_GAS_FOR_RECEIVER: Gas = Gas::from_tgas(5);
+const GAS_FOR_MIGRATE: Gas = Gas::from_tgas(100);
+
+// State layout version, stored next to the contract state so `migrate` knows what to read.
+// Version 1 is the original deployment, which predates this key.
+const STATE_VERSION_KEY: &[u8] = b"state_version";
+const STATE_VERSION: u32 = 2;
+
+// Metadata spec version and the largest accepted `icon` data URL
+const FT_METADATA_SPEC: &str = "ft-1.0.0";
+const MAX_ICON_LENGTH: usize = 16 * 1024;
 
 // Token metadata structure
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, schemars::JsonSchema)]
@@ -27,32 +40,179 @@ pub struct FungibleTokenMetadata {
     pub decimals: u8,
 }
 
-// Events for NEP-297 standard
+// NEP-141 event standard name and version
+const FT_EVENT_STANDARD: &str = "nep141";
+const FT_EVENT_VERSION: &str = "1.0.0";
+
+// NEP-297 event envelope: `EVENT_JSON:{"standard","version","event","data":[...]}`
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct NearEvent<'a, T: Serialize> {
+    standard: &'a str,
+    version: &'a str,
+    event: &'a str,
+    data: &'a [T],
+}
+
+fn emit_event<T: Serialize>(standard: &str, version: &str, event: &str, data: &[T]) {
+    let event = NearEvent { standard, version, event, data };
+    env::log_str(&format!(
+        "EVENT_JSON:{}",
+        near_sdk::serde_json::to_string(&event).unwrap()
+    ));
+}
+
+// Events for this contract's extensions beyond NEP-141
+const UNITE_EVENT_STANDARD: &str = "unite";
+const UNITE_EVENT_VERSION: &str = "1.0.0";
+
+// Events for NEP-141 standard
 #[derive(Serialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct FtTransferEvent {
     pub old_owner_id: AccountId,
     pub new_owner_id: AccountId,
-    pub amount: String,
+    pub amount: U128,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub memo: Option<String>,
 }
 
+impl FtTransferEvent {
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    pub fn emit_many(data: &[FtTransferEvent]) {
+        emit_event(FT_EVENT_STANDARD, FT_EVENT_VERSION, "ft_transfer", data)
+    }
+}
+
 #[derive(Serialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct FtMintEvent {
     pub owner_id: AccountId,
-    pub amount: String,
+    pub amount: U128,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub memo: Option<String>,
 }
 
+impl FtMintEvent {
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    pub fn emit_many(data: &[FtMintEvent]) {
+        emit_event(FT_EVENT_STANDARD, FT_EVENT_VERSION, "ft_mint", data)
+    }
+}
+
 #[derive(Serialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct FtBurnEvent {
     pub owner_id: AccountId,
-    pub amount: String,
+    pub amount: U128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+impl FtBurnEvent {
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    pub fn emit_many(data: &[FtBurnEvent]) {
+        emit_event(FT_EVENT_STANDARD, FT_EVENT_VERSION, "ft_burn", data)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtApproveEvent {
+    pub owner_id: AccountId,
+    pub spender_id: AccountId,
+    pub amount: U128,
+}
+
+impl FtApproveEvent {
+    pub fn emit(self) {
+        emit_event(UNITE_EVENT_STANDARD, UNITE_EVENT_VERSION, "ft_approve", &[self])
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtMetadataUpdateEvent {
+    pub updated_by: AccountId,
+    pub name: String,
+    pub symbol: String,
+    pub icon_changed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reference: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reference_hash: Option<String>,
+}
+
+impl FtMetadataUpdateEvent {
+    pub fn emit(self) {
+        emit_event(UNITE_EVENT_STANDARD, UNITE_EVENT_VERSION, "ft_metadata_update", &[self])
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AccountFreezeEvent {
+    pub account_id: AccountId,
+}
+
+impl AccountFreezeEvent {
+    pub fn emit(self, frozen: bool) {
+        let event = if frozen { "account_frozen" } else { "account_unfrozen" };
+        emit_event(UNITE_EVENT_STANDARD, UNITE_EVENT_VERSION, event, &[self])
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ForceTransferEvent {
+    pub executed_by: AccountId,
+    pub old_owner_id: AccountId,
+    pub new_owner_id: AccountId,
+    pub amount: U128,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub memo: Option<String>,
 }
 
+impl ForceTransferEvent {
+    pub fn emit(self) {
+        emit_event(UNITE_EVENT_STANDARD, UNITE_EVENT_VERSION, "force_transfer", &[self])
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FaucetModeEvent {
+    pub updated_by: AccountId,
+    pub enabled: bool,
+}
+
+impl FaucetModeEvent {
+    pub fn emit(self) {
+        emit_event(UNITE_EVENT_STANDARD, UNITE_EVENT_VERSION, "faucet_mode_update", &[self])
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SnapshotEvent {
+    pub snapshot_id: u64,
+}
+
+impl SnapshotEvent {
+    pub fn emit(self) {
+        emit_event(UNITE_EVENT_STANDARD, UNITE_EVENT_VERSION, "snapshot", &[self])
+    }
+}
+
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
 pub struct UniteToken {
@@ -62,16 +222,57 @@ pub struct UniteToken {
     total_supply: Balance,
     /// The bytes for the largest possible account ID that can be registered on the contract
     bytes_for_longest_account_id: u32,
+    /// The bytes for one checkpoint of the largest possible account ID
+    bytes_for_checkpoint: u32,
+    /// The bytes for the checkpoint count of the largest possible account ID
+    bytes_for_checkpoint_count: u32,
+    /// The bytes for the storage deposit record of the largest possible account ID
+    bytes_for_storage_deposit: u32,
+    /// Number of registered accounts, each can write one checkpoint per snapshot
+    registered_accounts: u64,
+    /// AccountID -> storage deposit it paid when registering, refunded on unregister
+    storage_deposits: LookupMap<AccountId, Balance>,
     /// Metadata for the token
     metadata: FungibleTokenMetadata,
-    /// Owner of the contract (can mint tokens)
+    /// Owner of the contract (manages the minter set)
+    owner_id: AccountId,
+    /// Accounts allowed to mint in addition to the owner
+    minters: UnorderedSet<AccountId>,
+    /// Optional hard cap on the total supply
+    max_supply: Option<Balance>,
+    /// When enabled anyone can mint (testnet faucet behaviour)
+    faucet_mode: bool,
+    /// (owner, spender) -> amount the spender may pull with `ft_transfer_from`
+    allowances: LookupMap<(AccountId, AccountId), Balance>,
+    /// Enables freezing and forced transfers for regulated deployments
+    compliance_enabled: bool,
+    /// Accounts that can neither send, receive nor burn tokens
+    frozen_accounts: LookupSet<AccountId>,
+    /// Id of the latest snapshot, 0 before the first one is taken
+    current_snapshot_id: u64,
+    /// AccountID -> number of checkpoints in `account_snapshots`
+    account_snapshot_counts: LookupMap<AccountId, u64>,
+    /// (AccountID, index) -> (snapshot id, balance before the first change after it),
+    /// ascending by index
+    account_snapshots: LookupMap<(AccountId, u64), (u64, Balance)>,
+    /// Same as `account_snapshots` for the total supply
+    total_supply_snapshots: Vector<(u64, Balance)>,
+}
+
+/// State layout of version 1 deployments, kept so `migrate` can read it
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct UniteTokenV1 {
+    accounts: LookupMap<AccountId, Balance>,
+    total_supply: Balance,
+    bytes_for_longest_account_id: u32,
+    metadata: FungibleTokenMetadata,
     owner_id: AccountId,
 }
 
 impl Default for FungibleTokenMetadata {
     fn default() -> Self {
         Self {
-            spec: "ft-1.0.0".to_string(),
+            spec: FT_METADATA_SPEC.to_string(),
             name: "UNITE V1".to_string(),
             symbol: "UNITE".to_string(),
             icon: None,
@@ -82,60 +283,162 @@ impl Default for FungibleTokenMetadata {
     }
 }
 
+impl FungibleTokenMetadata {
+    /// Checks the spec, the `reference`/`reference_hash` pairing and the icon size.
+    pub fn assert_valid(&self) {
+        require!(self.spec == FT_METADATA_SPEC, "Unsupported metadata spec");
+        require!(
+            self.reference.is_some() == self.reference_hash.is_some(),
+            "Reference and reference hash must be set together"
+        );
+        if let Some(reference_hash) = &self.reference_hash {
+            // Base64VecU8 decodes from a JSON string, reusing the SDK's base64 handling
+            let decoded = near_sdk::serde_json::from_value::<Base64VecU8>(
+                near_sdk::serde_json::Value::String(reference_hash.clone()),
+            );
+            require!(
+                matches!(decoded, Ok(hash) if hash.0.len() == 32),
+                "Reference hash must be a base64-encoded sha256 hash"
+            );
+        }
+        if let Some(icon) = &self.icon {
+            require!(icon.starts_with("data:"), "Icon must be a data URL");
+            require!(icon.len() <= MAX_ICON_LENGTH, "Icon data URL is too large");
+        }
+    }
+}
+
 #[near_bindgen]
 impl UniteToken {
     /// Initializes the contract with the given total supply owned by the given `owner_id`.
+    /// `max_supply` caps all future minting; `faucet_mode` lets anyone mint (testnets only);
+    /// `compliance_enabled` turns on account freezing and forced transfers.
     #[init]
-    pub fn new(owner_id: AccountId, total_supply: U128, metadata: Option<FungibleTokenMetadata>) -> Self {
+    pub fn new(
+        owner_id: AccountId,
+        total_supply: U128,
+        metadata: Option<FungibleTokenMetadata>,
+        max_supply: Option<U128>,
+        faucet_mode: Option<bool>,
+        compliance_enabled: Option<bool>,
+    ) -> Self {
         require!(!env::state_exists(), "Already initialized");
         
         let metadata = metadata.unwrap_or_default();
-        require!(metadata.spec == "ft-1.0.0", "Unsupported metadata spec");
+        metadata.assert_valid();
+
+        let max_supply: Option<Balance> = max_supply.map(|max| max.into());
+        if let Some(max) = max_supply {
+            require!(total_supply.0 <= max, "Initial supply exceeds max supply");
+        }
         
         let mut this = Self {
             accounts: LookupMap::new(b"a"),
             total_supply: total_supply.into(),
-            bytes_for_longest_account_id: 64,
+            bytes_for_longest_account_id: 0,
+            bytes_for_checkpoint: 0,
+            bytes_for_checkpoint_count: 0,
+            bytes_for_storage_deposit: 0,
+            registered_accounts: 1,
+            storage_deposits: LookupMap::new(b"d"),
             metadata,
             owner_id: owner_id.clone(),
+            minters: UnorderedSet::new(b"m"),
+            max_supply,
+            faucet_mode: faucet_mode.unwrap_or(false),
+            allowances: LookupMap::new(b"l"),
+            compliance_enabled: compliance_enabled.unwrap_or(false),
+            frozen_accounts: LookupSet::new(b"f"),
+            current_snapshot_id: 0,
+            account_snapshot_counts: LookupMap::new(b"c"),
+            account_snapshots: LookupMap::new(b"s"),
+            total_supply_snapshots: Vector::new(b"t"),
         };
+        this.measure_account_storage_usage();
         
-        // Set initial balance for owner
+        // Register the owner and set its initial balance. The deployer pays for the owner's
+        // storage, so there is no deposit to refund.
         this.accounts.insert(&owner_id, &total_supply.into());
+        this.storage_deposits.insert(&owner_id, &0);
         
         // Log mint event for initial supply
         this.emit_mint_event(&owner_id, total_supply.into(), Some("Initial supply".to_string()));
+        write_state_version();
         
         this
     }
 
-    /// Simple mint function - anyone can mint tokens
+    /// Mint tokens - restricted to the owner and minters unless faucet mode is enabled
     #[payable]
     pub fn mint(&mut self, account_id: AccountId, amount: U128) {
         self.assert_one_yocto();
+        if !self.faucet_mode {
+            self.assert_minter();
+        }
         
         let amount: Balance = amount.into();
         require!(amount > 0, "Amount must be positive");
+
+        let new_total_supply = self
+            .total_supply
+            .checked_add(amount)
+            .unwrap_or_else(|| env::panic_str("Total supply overflow"));
+        if let Some(max) = self.max_supply {
+            require!(new_total_supply <= max, "Max supply exceeded");
+        }
         
-        let balance = self.accounts.get(&account_id).unwrap_or(0);
-        self.accounts.insert(&account_id, &(balance + amount));
-        self.total_supply += amount;
+        let balance = self.internal_unwrap_balance_of(&account_id);
+        let new_balance = balance
+            .checked_add(amount)
+            .unwrap_or_else(|| env::panic_str("Balance overflow"));
+        self.internal_set_balance(&account_id, new_balance);
+        self.internal_set_total_supply(new_total_supply);
         
         self.emit_mint_event(&account_id, amount, None);
     }
 
+    /// Grant the minter role to an account (owner only)
+    #[payable]
+    pub fn add_minter(&mut self, account_id: AccountId) {
+        self.assert_one_yocto();
+        self.assert_owner();
+        self.minters.insert(&account_id);
+    }
+
+    /// Revoke the minter role from an account (owner only)
+    #[payable]
+    pub fn remove_minter(&mut self, account_id: AccountId) {
+        self.assert_one_yocto();
+        self.assert_owner();
+        self.minters.remove(&account_id);
+    }
+
+    /// Opens or closes minting to everyone (owner only)
+    #[payable]
+    pub fn set_faucet_mode(&mut self, enabled: bool) {
+        self.assert_one_yocto();
+        self.assert_owner();
+        self.faucet_mode = enabled;
+        FaucetModeEvent {
+            updated_by: env::predecessor_account_id(),
+            enabled,
+        }
+        .emit();
+    }
+
     /// Burn tokens from caller's account
     #[payable]
     pub fn burn(&mut self, amount: U128) {
         self.assert_one_yocto();
         let account_id = env::predecessor_account_id();
         let amount: Balance = amount.into();
+        self.assert_not_frozen(&account_id);
         
-        let balance = self.accounts.get(&account_id).unwrap_or(0);
+        let balance = self.internal_unwrap_balance_of(&account_id);
         require!(balance >= amount, "Insufficient balance to burn");
         
-        self.accounts.insert(&account_id, &(balance - amount));
-        self.total_supply -= amount;
+        self.internal_set_balance(&account_id, balance - amount);
+        self.internal_set_total_supply(self.total_supply - amount);
         
         self.emit_burn_event(&account_id, amount, None);
     }
@@ -153,43 +456,90 @@ impl UniteToken {
 
     // Helper functions for events
     fn emit_transfer_event(&self, old_owner_id: &AccountId, new_owner_id: &AccountId, amount: Balance, memo: Option<String>) {
-        let event = FtTransferEvent {
+        FtTransferEvent {
             old_owner_id: old_owner_id.clone(),
             new_owner_id: new_owner_id.clone(),
-            amount: amount.to_string(),
+            amount: amount.into(),
             memo,
-        };
-        
-        env::log_str(&format!(
-            "EVENT_JSON:{{\"standard\":\"nep171\",\"version\":\"1.0.0\",\"event\":\"ft_transfer\",\"data\":[{}]}}",
-            near_sdk::serde_json::to_string(&event).unwrap()
-        ));
+        }
+        .emit();
     }
 
     fn emit_mint_event(&self, owner_id: &AccountId, amount: Balance, memo: Option<String>) {
-        let event = FtMintEvent {
+        FtMintEvent {
             owner_id: owner_id.clone(),
-            amount: amount.to_string(),
+            amount: amount.into(),
             memo,
-        };
-        
-        env::log_str(&format!(
-            "EVENT_JSON:{{\"standard\":\"nep171\",\"version\":\"1.0.0\",\"event\":\"ft_mint\",\"data\":[{}]}}",
-            near_sdk::serde_json::to_string(&event).unwrap()
-        ));
+        }
+        .emit();
     }
 
     fn emit_burn_event(&self, owner_id: &AccountId, amount: Balance, memo: Option<String>) {
-        let event = FtBurnEvent {
+        FtBurnEvent {
             owner_id: owner_id.clone(),
-            amount: amount.to_string(),
+            amount: amount.into(),
             memo,
-        };
-        
-        env::log_str(&format!(
-            "EVENT_JSON:{{\"standard\":\"nep171\",\"version\":\"1.0.0\",\"event\":\"ft_burn\",\"data\":[{}]}}",
-            near_sdk::serde_json::to_string(&event).unwrap()
-        ));
+        }
+        .emit();
+    }
+
+    /// Measures the storage taken by one balance entry, one storage deposit record, one
+    /// checkpoint and one checkpoint count, each keyed by the longest possible account ID
+    fn measure_account_storage_usage(&mut self) {
+        let tmp_account_id: AccountId = "a".repeat(64).parse().unwrap();
+
+        let initial_storage_usage = env::storage_usage();
+        self.accounts.insert(&tmp_account_id, &0u128);
+        self.bytes_for_longest_account_id = (env::storage_usage() - initial_storage_usage) as u32;
+        self.accounts.remove(&tmp_account_id);
+
+        let initial_storage_usage = env::storage_usage();
+        self.storage_deposits.insert(&tmp_account_id, &0);
+        self.bytes_for_storage_deposit = (env::storage_usage() - initial_storage_usage) as u32;
+        self.storage_deposits.remove(&tmp_account_id);
+
+        let initial_storage_usage = env::storage_usage();
+        self.account_snapshots.insert(&(tmp_account_id.clone(), 0), &(0, 0));
+        self.bytes_for_checkpoint = (env::storage_usage() - initial_storage_usage) as u32;
+        self.account_snapshots.remove(&(tmp_account_id.clone(), 0));
+
+        let initial_storage_usage = env::storage_usage();
+        self.account_snapshot_counts.insert(&tmp_account_id, &0);
+        self.bytes_for_checkpoint_count = (env::storage_usage() - initial_storage_usage) as u32;
+        self.account_snapshot_counts.remove(&tmp_account_id);
+    }
+
+    /// Writes a registered account's balance, checkpointing it for the current snapshot first
+    fn internal_set_balance(&mut self, account_id: &AccountId, balance: Balance) {
+        self.update_account_snapshot(account_id);
+        self.accounts.insert(account_id, &balance);
+    }
+
+    fn internal_set_total_supply(&mut self, total_supply: Balance) {
+        self.update_total_supply_snapshot();
+        self.total_supply = total_supply;
+    }
+
+    fn internal_unwrap_balance_of(&self, account_id: &AccountId) -> Balance {
+        match self.accounts.get(account_id) {
+            Some(balance) => balance,
+            None => env::panic_str(&format!("The account {} is not registered", account_id)),
+        }
+    }
+
+    fn assert_owner(&self) {
+        require!(
+            env::predecessor_account_id() == self.owner_id,
+            "Only owner can call this method"
+        );
+    }
+
+    fn assert_minter(&self) {
+        let caller = env::predecessor_account_id();
+        require!(
+            caller == self.owner_id || self.minters.contains(&caller),
+            "Only owner or minters can mint"
+        );
     }
 
     fn assert_one_yocto(&self) {
@@ -232,14 +582,28 @@ impl UniteToken {
         msg: String,
     ) -> PromiseOrValue<U128> {
         self.assert_one_yocto();
+        require!(
+            env::prepaid_gas()
+                >= GAS_FOR_FT_TRANSFER_CALL
+                    .saturating_add(GAS_FOR_RESOLVE_TRANSFER)
+                    .saturating_add(MIN_GAS_FOR_RECEIVER),
+            "More gas is required"
+        );
         let sender_id = env::predecessor_account_id();
         let amount: Balance = amount.into();
         
         self.internal_transfer(&sender_id, &receiver_id, amount, memo.clone());
         
+        // The receiver gets whatever is left after the callback and our own overhead
+        let receiver_gas = env::prepaid_gas()
+            .saturating_sub(env::used_gas())
+            .saturating_sub(GAS_FOR_RESOLVE_TRANSFER)
+            .saturating_sub(GAS_FOR_FT_TRANSFER_CALL);
+        require!(receiver_gas >= MIN_GAS_FOR_RECEIVER, "More gas is required");
+
         // Call ft_on_transfer on the receiver
         let promise = ext_ft_receiver::ext(receiver_id.clone())
-            .with_static_gas(GAS_FOR_FT_TRANSFER_CALL)
+            .with_static_gas(receiver_gas)
             .with_attached_deposit(NearToken::from_yoctonear(0))
             .ft_on_transfer(sender_id.clone(), amount.into(), msg);
         
@@ -254,22 +618,34 @@ impl UniteToken {
 
     /// Internal transfer function
     fn internal_transfer(&mut self, sender_id: &AccountId, receiver_id: &AccountId, amount: Balance, memo: Option<String>) {
+        self.assert_not_frozen(sender_id);
+        self.assert_not_frozen(receiver_id);
+        self.internal_transfer_unchecked(sender_id, receiver_id, amount, memo);
+    }
+
+    /// Moves balances without compliance checks; only `force_transfer` calls this directly
+    fn internal_transfer_unchecked(&mut self, sender_id: &AccountId, receiver_id: &AccountId, amount: Balance, memo: Option<String>) {
         require!(amount > 0, "Amount must be positive");
         require!(sender_id != receiver_id, "Sender and receiver should be different");
         
-        let sender_balance = self.accounts.get(sender_id).unwrap_or(0);
+        let sender_balance = self.internal_unwrap_balance_of(sender_id);
         require!(sender_balance >= amount, "Insufficient balance");
         
-        // Update balances
-        self.accounts.insert(sender_id, &(sender_balance - amount));
-        let receiver_balance = self.accounts.get(receiver_id).unwrap_or(0);
-        self.accounts.insert(receiver_id, &(receiver_balance + amount));
+        // Update balances; the receiver must already be registered
+        let receiver_balance = self.internal_unwrap_balance_of(receiver_id);
+        let new_receiver_balance = receiver_balance
+            .checked_add(amount)
+            .unwrap_or_else(|| env::panic_str("Balance overflow"));
+        self.internal_set_balance(sender_id, sender_balance - amount);
+        self.internal_set_balance(receiver_id, new_receiver_balance);
         
         // Emit transfer event
         self.emit_transfer_event(sender_id, receiver_id, amount, memo);
     }
 
-    /// Callback to resolve transfer
+    /// Callback to resolve transfer. Refunds as much of the unused amount as the
+    /// receiver still holds, burns it if the sender has unregistered meanwhile,
+    /// and returns the amount that was actually used.
     #[private]
     pub fn ft_resolve_transfer(
         &mut self,
@@ -292,145 +668,696 @@ impl UniteToken {
         };
 
         if unused_amount > 0 {
+            // The receiver may have spent or unregistered part of the tokens already
             let receiver_balance = self.accounts.get(&receiver_id).unwrap_or(0);
-            if receiver_balance >= unused_amount {
-                self.accounts.insert(&receiver_id, &(receiver_balance - unused_amount));
-                let sender_balance = self.accounts.get(&sender_id).unwrap_or(0);
-                self.accounts.insert(&sender_id, &(sender_balance + unused_amount));
-                
-                self.emit_transfer_event(&receiver_id, &sender_id, unused_amount, Some("Refund".to_string()));
+            if receiver_balance > 0 {
+                let refund_amount = std::cmp::min(receiver_balance, unused_amount);
+                self.internal_set_balance(&receiver_id, receiver_balance - refund_amount);
+
+                if let Some(sender_balance) = self.accounts.get(&sender_id) {
+                    let new_sender_balance = sender_balance
+                        .checked_add(refund_amount)
+                        .unwrap_or_else(|| env::panic_str("Balance overflow"));
+                    self.internal_set_balance(&sender_id, new_sender_balance);
+                    self.emit_transfer_event(&receiver_id, &sender_id, refund_amount, Some("Refund".to_string()));
+                    return (amount - refund_amount).into();
+                }
+
+                // The sender unregistered in the meantime, so the refund is burned
+                self.internal_set_total_supply(self.total_supply - refund_amount);
+                log!("The account of the sender was deleted");
+                self.emit_burn_event(&receiver_id, refund_amount, Some("Refund".to_string()));
             }
         }
 
-        unused_amount.into()
+        amount.into()
     }
 }
 
-// NEP-148 Metadata Standard Implementation
+// Upgrades and state migration
 #[near_bindgen]
 impl UniteToken {
-    pub fn ft_metadata(&self) -> FungibleTokenMetadata {
-        self.metadata.clone()
+    /// Deploys the new contract code passed as raw input (owner only) and calls `migrate` on it
+    #[payable]
+    pub fn upgrade(&self) -> Promise {
+        self.assert_one_yocto();
+        self.assert_owner();
+        let code = env::input().unwrap_or_else(|| env::panic_str("Missing contract code"));
+        Promise::new(env::current_account_id())
+            .deploy_contract(code)
+            .function_call(
+                "migrate".to_string(),
+                vec![],
+                NearToken::from_yoctonear(0),
+                GAS_FOR_MIGRATE,
+            )
     }
-}
 
-// Additional view methods
-#[near_bindgen]
-impl UniteToken {
-    pub fn get_owner(&self) -> AccountId {
-        self.owner_id.clone()
-    }
+    /// Converts the stored state to the current layout. Called by `upgrade` after deploying.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let version = env::storage_read(STATE_VERSION_KEY)
+            .map(|bytes| u32::try_from_slice(&bytes).expect("Invalid state version"))
+            .unwrap_or(1);
 
-    pub fn storage_balance_bounds(&self) -> StorageBalanceBounds {
-        let required_storage_balance = Balance::from(self.bytes_for_longest_account_id) * env::storage_byte_cost().as_yoctonear();
-        StorageBalanceBounds {
-            min: required_storage_balance.into(),
-            max: Some(required_storage_balance.into()),
-        }
-    }
+        let this = match version {
+            1 => {
+                let old: UniteTokenV1 = env::state_read().expect("Failed to read old state");
+                let mut this = Self {
+                    accounts: old.accounts,
+                    total_supply: old.total_supply,
+                    bytes_for_longest_account_id: old.bytes_for_longest_account_id,
+                    bytes_for_checkpoint: 0,
+                    bytes_for_checkpoint_count: 0,
+                    bytes_for_storage_deposit: 0,
+                    // Version 1 created accounts implicitly, they are counted once they register
+                    registered_accounts: 0,
+                    // Nobody paid for storage in version 1, so its accounts have no deposit
+                    storage_deposits: LookupMap::new(b"d"),
+                    metadata: old.metadata,
+                    owner_id: old.owner_id,
+                    minters: UnorderedSet::new(b"m"),
+                    max_supply: None,
+                    // Version 1 let anyone mint, keep that behaviour until the owner calls
+                    // `set_faucet_mode(false)`
+                    faucet_mode: true,
+                    allowances: LookupMap::new(b"l"),
+                    compliance_enabled: false,
+                    frozen_accounts: LookupSet::new(b"f"),
+                    current_snapshot_id: 0,
+                    account_snapshot_counts: LookupMap::new(b"c"),
+                    account_snapshots: LookupMap::new(b"s"),
+                    total_supply_snapshots: Vector::new(b"t"),
+                };
+                // Version 1 hardcoded 64 bytes, measure the real cost of each entry
+                this.measure_account_storage_usage();
+                this
+            }
+            STATE_VERSION => env::state_read().expect("Failed to read state"),
+            _ => env::panic_str("Unknown state version"),
+        };
 
-    pub fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
-        if self.accounts.contains_key(&account_id) {
-            Some(StorageBalance {
-                total: self.storage_balance_bounds().min,
-                available: 0.into(),
-            })
-        } else {
-            None
-        }
+        write_state_version();
+        log!("Migrated state from version {} to {}", version, STATE_VERSION);
+        this
     }
-}
 
-#[derive(Serialize, Deserialize, schemars::JsonSchema)]
-#[serde(crate = "near_sdk::serde")]
-pub struct StorageBalance {
-    #[schemars(with = "String")]
-    pub total: U128,
-    #[schemars(with = "String")]
-    pub available: U128,
+    pub fn get_state_version(&self) -> u32 {
+        STATE_VERSION
+    }
 }
 
-#[derive(Serialize, Deserialize, schemars::JsonSchema)]
-#[serde(crate = "near_sdk::serde")]
-pub struct StorageBalanceBounds {
-    #[schemars(with = "String")]
-    pub min: U128,
-    #[schemars(with = "String")]
-    pub max: Option<U128>,
+fn write_state_version() {
+    env::storage_write(STATE_VERSION_KEY, &borsh::to_vec(&STATE_VERSION).unwrap());
 }
 
-// External contract interfaces
-#[near_sdk::ext_contract(ext_ft_receiver)]
-pub trait FungibleTokenReceiver {
-    fn ft_on_transfer(&mut self, sender_id: AccountId, amount: U128, msg: String) -> PromiseOrValue<U128>;
+/// Lower bound over `len` ascending checkpoints: index of the first one written at or after
+/// `snapshot_id`, or `len` if there is none
+fn first_checkpoint_at(len: u64, snapshot_id: u64, id_at: impl Fn(u64) -> u64) -> u64 {
+    let (mut low, mut high) = (0, len);
+    while low < high {
+        let mid = (low + high) / 2;
+        if id_at(mid) < snapshot_id {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    low
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use near_sdk::test_utils::{accounts, VMContextBuilder};
-    use near_sdk::{testing_env, Balance};
+// Snapshot extension, checkpoints are written lazily on the first change after each snapshot
+#[near_bindgen]
+impl UniteToken {
+    /// Takes a new snapshot of all balances and the total supply (owner only). The attached
+    /// deposit must cover the most the snapshot can write, one checkpoint per registered
+    /// account and one for the total supply; the excess is refunded.
+    #[payable]
+    pub fn snapshot(&mut self) -> u64 {
+        require!(
+            env::attached_deposit() >= NearToken::from_yoctonear(1),
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        self.assert_owner();
+        // A total supply checkpoint is smaller than an account's, so it is charged as one
+        let checkpoints = self.registered_accounts + 1;
+        self.internal_refund_storage_deposit(0, u64::from(self.bytes_for_checkpoint) * checkpoints);
 
-    fn get_context(predecessor_account_id: AccountId, attached_deposit: Balance) -> VMContextBuilder {
-        let mut builder = VMContextBuilder::new();
-        builder
-            .current_account_id(accounts(0))
-            .signer_account_id(predecessor_account_id.clone())
-            .predecessor_account_id(predecessor_account_id)
-            .attached_deposit(NearToken::from_yoctonear(attached_deposit));
-        builder
+        self.current_snapshot_id += 1;
+        SnapshotEvent {
+            snapshot_id: self.current_snapshot_id,
+        }
+        .emit();
+        self.current_snapshot_id
     }
 
-    #[test]
-    fn test_new() {
-        let mut context = get_context(accounts(1), 0);
-        testing_env!(context.build());
-
-        let total_supply = 1_000_000_000_000_000u128;
-        let contract = UniteToken::new(accounts(1), total_supply.into(), None);
-        
-        assert_eq!(contract.ft_total_supply().0, total_supply);
-        assert_eq!(contract.ft_balance_of(accounts(1)).0, total_supply);
-        assert_eq!(contract.get_owner(), accounts(1));
+    pub fn get_current_snapshot_id(&self) -> u64 {
+        self.current_snapshot_id
     }
 
-    #[test]
-    fn test_transfer() {
-        let mut context = get_context(accounts(2), 1);
-        testing_env!(context.build());
-
-        let total_supply = 1_000_000_000_000_000u128;
-        let mut contract = UniteToken::new(accounts(2), total_supply.into(), None);
-        let transfer_amount = 1_000_000_000_000u128;
-
-        contract.ft_transfer(accounts(1), transfer_amount.into(), None);
-        
-        assert_eq!(contract.ft_balance_of(accounts(2)).0, total_supply - transfer_amount);
-        assert_eq!(contract.ft_balance_of(accounts(1)).0, transfer_amount);
+    /// Returns the balance `account_id` held when `snapshot_id` was taken
+    pub fn ft_balance_of_at(&self, account_id: AccountId, snapshot_id: u64) -> U128 {
+        self.assert_valid_snapshot_id(snapshot_id);
+        let count = self.account_snapshot_counts.get(&account_id).unwrap_or(0);
+        let index = first_checkpoint_at(count, snapshot_id, |index| {
+            self.account_snapshots.get(&(account_id.clone(), index)).unwrap().0
+        });
+        let checkpoint = (index < count)
+            .then(|| self.account_snapshots.get(&(account_id.clone(), index)).unwrap().1);
+        checkpoint
+            .unwrap_or_else(|| self.accounts.get(&account_id).unwrap_or(0))
+            .into()
     }
 
-    #[test]
-    fn test_mint() {
-        let mut context = get_context(accounts(1), 1);
-        testing_env!(context.build());
+    /// Returns the total supply when `snapshot_id` was taken
+    pub fn ft_total_supply_at(&self, snapshot_id: u64) -> U128 {
+        self.assert_valid_snapshot_id(snapshot_id);
+        let index = first_checkpoint_at(self.total_supply_snapshots.len(), snapshot_id, |index| {
+            self.total_supply_snapshots.get(index).unwrap().0
+        });
+        self.total_supply_snapshots
+            .get(index)
+            .map_or(self.total_supply, |(_, total_supply)| total_supply)
+            .into()
+    }
 
-        let total_supply = 1_000_000_000_000_000u128;
-        let mut contract = UniteToken::new(accounts(1), total_supply.into(), None);
-        let mint_amount = 1_000_000_000_000u128;
+    fn assert_valid_snapshot_id(&self, snapshot_id: u64) {
+        require!(
+            snapshot_id > 0 && snapshot_id <= self.current_snapshot_id,
+            "Invalid snapshot id"
+        );
+    }
 
-        contract.mint(accounts(2), mint_amount.into());
-        
-        assert_eq!(contract.ft_total_supply().0, total_supply + mint_amount);
-        assert_eq!(contract.ft_balance_of(accounts(2)).0, mint_amount);
+    /// Records the balance before its first change since the current snapshot
+    fn update_account_snapshot(&mut self, account_id: &AccountId) {
+        if self.current_snapshot_id == 0 {
+            return;
+        }
+        let count = self.account_snapshot_counts.get(account_id).unwrap_or(0);
+        let needs_checkpoint = match count {
+            0 => true,
+            count => self.account_snapshots.get(&(account_id.clone(), count - 1)).unwrap().0 < self.current_snapshot_id,
+        };
+        if needs_checkpoint {
+            // Snapshots only pay for registered accounts' checkpoints, so accounts carried over
+            // from version 1 have to register before they can write one
+            require!(
+                self.storage_deposits.contains_key(account_id),
+                format!("The account {} must register with storage_deposit before its balance can change", account_id)
+            );
+            let balance = self.accounts.get(account_id).unwrap_or(0);
+            self.account_snapshots
+                .insert(&(account_id.clone(), count), &(self.current_snapshot_id, balance));
+            self.account_snapshot_counts.insert(account_id, &(count + 1));
+        }
     }
 
-    #[test]
+    fn update_total_supply_snapshot(&mut self) {
+        if self.current_snapshot_id == 0 {
+            return;
+        }
+        let needs_checkpoint = match self.total_supply_snapshots.len() {
+            0 => true,
+            len => self.total_supply_snapshots.get(len - 1).unwrap().0 < self.current_snapshot_id,
+        };
+        if needs_checkpoint {
+            self.total_supply_snapshots
+                .push(&(self.current_snapshot_id, self.total_supply));
+        }
+    }
+}
+
+// Compliance extension, only usable when enabled at init
+#[near_bindgen]
+impl UniteToken {
+    /// Freezes an account so it can no longer send, receive or burn tokens (owner only)
+    #[payable]
+    pub fn freeze_account(&mut self, account_id: AccountId) {
+        self.assert_one_yocto();
+        self.assert_owner();
+        self.assert_compliance_enabled();
+        if self.frozen_accounts.insert(&account_id) {
+            AccountFreezeEvent { account_id }.emit(true);
+        }
+    }
+
+    /// Lifts a freeze (owner only)
+    #[payable]
+    pub fn unfreeze_account(&mut self, account_id: AccountId) {
+        self.assert_one_yocto();
+        self.assert_owner();
+        self.assert_compliance_enabled();
+        if self.frozen_accounts.remove(&account_id) {
+            AccountFreezeEvent { account_id }.emit(false);
+        }
+    }
+
+    pub fn is_frozen(&self, account_id: AccountId) -> bool {
+        self.frozen_accounts.contains(&account_id)
+    }
+
+    pub fn is_compliance_enabled(&self) -> bool {
+        self.compliance_enabled
+    }
+
+    /// Moves tokens between accounts regardless of freezes (owner only), logging an audit event
+    #[payable]
+    pub fn force_transfer(
+        &mut self,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+    ) {
+        self.assert_one_yocto();
+        self.assert_owner();
+        self.assert_compliance_enabled();
+
+        self.internal_transfer_unchecked(&sender_id, &receiver_id, amount.0, memo.clone());
+
+        ForceTransferEvent {
+            executed_by: env::predecessor_account_id(),
+            old_owner_id: sender_id,
+            new_owner_id: receiver_id,
+            amount,
+            memo,
+        }
+        .emit();
+    }
+
+    fn assert_compliance_enabled(&self) {
+        require!(self.compliance_enabled, "Compliance module is disabled");
+    }
+
+    fn assert_not_frozen(&self, account_id: &AccountId) {
+        if self.compliance_enabled && self.frozen_accounts.contains(account_id) {
+            env::panic_str(&format!("The account {} is frozen", account_id));
+        }
+    }
+}
+
+// Allowance extension, lets contracts such as the escrow pull pre-approved funds
+#[near_bindgen]
+impl UniteToken {
+    /// Sets the amount `spender_id` may transfer out of the caller's account.
+    /// Approving 0 removes the allowance and refunds the storage it took. The attached
+    /// deposit must cover any storage a new allowance takes; the excess is refunded.
+    #[payable]
+    pub fn ft_approve(&mut self, spender_id: AccountId, amount: U128) {
+        require!(
+            env::attached_deposit() >= NearToken::from_yoctonear(1),
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        let owner_id = env::predecessor_account_id();
+        require!(owner_id != spender_id, "Owner and spender should be different");
+
+        let initial_storage_usage = env::storage_usage();
+        let key = (owner_id.clone(), spender_id.clone());
+        if amount.0 == 0 {
+            self.allowances.remove(&key);
+        } else {
+            self.allowances.insert(&key, &amount.0);
+        }
+        self.internal_refund_storage_deposit(initial_storage_usage, env::storage_usage());
+
+        FtApproveEvent {
+            owner_id,
+            spender_id,
+            amount,
+        }
+        .emit();
+    }
+
+    /// Returns how much `spender_id` may still transfer out of `owner_id`'s account.
+    pub fn ft_allowance(&self, owner_id: AccountId, spender_id: AccountId) -> U128 {
+        self.allowances.get(&(owner_id, spender_id)).unwrap_or(0).into()
+    }
+
+    /// Transfers `amount` from `owner_id` to `receiver_id`, spending the caller's allowance.
+    #[payable]
+    pub fn ft_transfer_from(
+        &mut self,
+        owner_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+    ) {
+        self.assert_one_yocto();
+        let spender_id = env::predecessor_account_id();
+        let amount: Balance = amount.into();
+        self.assert_not_frozen(&spender_id);
+
+        let key = (owner_id.clone(), spender_id);
+        let allowance = self.allowances.get(&key).unwrap_or(0);
+        require!(allowance >= amount, "Insufficient allowance");
+        if allowance == amount {
+            // The owner paid for the entry when approving, so the freed storage goes back to them
+            let initial_storage_usage = env::storage_usage();
+            self.allowances.remove(&key);
+            self.internal_refund_freed_storage(&owner_id, initial_storage_usage);
+        } else {
+            self.allowances.insert(&key, &(allowance - amount));
+        }
+
+        self.internal_transfer(&owner_id, &receiver_id, amount, memo);
+    }
+
+    /// Charges the caller for storage added going from `initial_storage_usage` to `storage_usage`
+    /// and refunds the rest, plus the cost of any storage freed
+    fn internal_refund_storage_deposit(&self, initial_storage_usage: u64, storage_usage: u64) {
+        let byte_cost = env::storage_byte_cost().as_yoctonear();
+        let required_cost = byte_cost * Balance::from(storage_usage.saturating_sub(initial_storage_usage));
+        let freed_cost = byte_cost * Balance::from(initial_storage_usage.saturating_sub(storage_usage));
+        let attached_deposit = env::attached_deposit().as_yoctonear();
+        require!(
+            attached_deposit >= required_cost,
+            format!("Must attach {} yoctoNEAR to cover storage", required_cost)
+        );
+
+        let refund = attached_deposit - required_cost + freed_cost;
+        if refund > 0 {
+            Promise::new(env::predecessor_account_id()).transfer(NearToken::from_yoctonear(refund));
+        }
+    }
+
+    /// Refunds `account_id` the cost of storage freed since `initial_storage_usage`
+    fn internal_refund_freed_storage(&self, account_id: &AccountId, initial_storage_usage: u64) {
+        let storage_freed = initial_storage_usage.saturating_sub(env::storage_usage());
+        let refund = env::storage_byte_cost().as_yoctonear() * Balance::from(storage_freed);
+        if refund > 0 {
+            Promise::new(account_id.clone()).transfer(NearToken::from_yoctonear(refund));
+        }
+    }
+}
+
+// NEP-148 Metadata Standard Implementation
+#[near_bindgen]
+impl UniteToken {
+    pub fn ft_metadata(&self) -> FungibleTokenMetadata {
+        self.metadata.clone()
+    }
+
+    /// Replaces the token metadata (owner only). `decimals` can never change.
+    /// The attached deposit must cover any extra storage; the excess is refunded.
+    #[payable]
+    pub fn set_metadata(&mut self, metadata: FungibleTokenMetadata) {
+        require!(
+            env::attached_deposit() >= NearToken::from_yoctonear(1),
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        self.assert_owner();
+        metadata.assert_valid();
+        require!(
+            metadata.decimals == self.metadata.decimals,
+            "Decimals cannot be changed"
+        );
+
+        // The metadata lives in the contract state, which is only written back once the call
+        // returns, so the storage it takes is measured from its serialized size
+        let initial_storage_usage = borsh::to_vec(&self.metadata).unwrap().len() as u64;
+        let storage_usage = borsh::to_vec(&metadata).unwrap().len() as u64;
+        let icon_changed = metadata.icon != self.metadata.icon;
+        self.metadata = metadata;
+        self.internal_refund_storage_deposit(initial_storage_usage, storage_usage);
+
+        FtMetadataUpdateEvent {
+            updated_by: env::predecessor_account_id(),
+            name: self.metadata.name.clone(),
+            symbol: self.metadata.symbol.clone(),
+            icon_changed,
+            reference: self.metadata.reference.clone(),
+            reference_hash: self.metadata.reference_hash.clone(),
+        }
+        .emit();
+    }
+}
+
+// Additional view methods
+#[near_bindgen]
+impl UniteToken {
+    pub fn get_owner(&self) -> AccountId {
+        self.owner_id.clone()
+    }
+
+    pub fn is_minter(&self, account_id: AccountId) -> bool {
+        account_id == self.owner_id || self.minters.contains(&account_id)
+    }
+
+    pub fn get_minters(&self) -> Vec<AccountId> {
+        self.minters.to_vec()
+    }
+
+    pub fn get_max_supply(&self) -> Option<U128> {
+        self.max_supply.map(|max| max.into())
+    }
+
+    pub fn is_faucet_mode(&self) -> bool {
+        self.faucet_mode
+    }
+
+}
+
+// NEP-145 Storage Management Implementation
+#[near_bindgen]
+impl UniteToken {
+    /// Registers `account_id` (or the caller) by paying for its balance entry. Accounts
+    /// carried over from version 1 keep their balance. Any deposit above the required
+    /// minimum is refunded to the caller.
+    #[payable]
+    pub fn storage_deposit(
+        &mut self,
+        account_id: Option<AccountId>,
+        registration_only: Option<bool>,
+    ) -> StorageBalance {
+        // Storage balance is fixed (min == max), so `registration_only` changes nothing
+        let _ = registration_only;
+        let amount = env::attached_deposit().as_yoctonear();
+        let predecessor_id = env::predecessor_account_id();
+        let account_id = account_id.unwrap_or_else(|| predecessor_id.clone());
+
+        if self.storage_deposits.contains_key(&account_id) {
+            log!("The account is already registered, refunding the deposit");
+            if amount > 0 {
+                Promise::new(predecessor_id).transfer(NearToken::from_yoctonear(amount));
+            }
+        } else {
+            let min_balance = self.storage_balance_bounds().min.0;
+            require!(
+                amount >= min_balance,
+                "The attached deposit is less than the minimum storage balance"
+            );
+
+            if !self.accounts.contains_key(&account_id) {
+                self.accounts.insert(&account_id, &0);
+            }
+            self.storage_deposits.insert(&account_id, &min_balance);
+            self.registered_accounts += 1;
+            let refund = amount - min_balance;
+            if refund > 0 {
+                Promise::new(predecessor_id).transfer(NearToken::from_yoctonear(refund));
+            }
+        }
+
+        self.internal_storage_balance_of(&account_id).unwrap()
+    }
+
+    /// Withdraws unused storage deposit. Registration costs exactly the minimum,
+    /// so nothing is ever available and only `None` or `0` succeed.
+    #[payable]
+    pub fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+        self.assert_one_yocto();
+        let predecessor_id = env::predecessor_account_id();
+        let storage_balance = self
+            .internal_storage_balance_of(&predecessor_id)
+            .unwrap_or_else(|| {
+                env::panic_str(&format!("The account {} is not registered", predecessor_id))
+            });
+        if let Some(amount) = amount {
+            require!(
+                amount.0 == 0,
+                "The amount is greater than the available storage balance"
+            );
+        }
+        storage_balance
+    }
+
+    /// Unregisters the caller and returns the storage deposit it paid, up to the cost of the
+    /// storage freed. With `force` the remaining token balance is burned, otherwise the
+    /// balance must be zero.
+    #[payable]
+    pub fn storage_unregister(&mut self, force: Option<bool>) -> bool {
+        self.assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let force = force.unwrap_or(false);
+
+        let Some(balance) = self.accounts.get(&account_id) else {
+            log!("The account {} is not registered", account_id);
+            return false;
+        };
+        require!(
+            balance == 0 || force,
+            "Can't unregister the account with the positive balance without force"
+        );
+        if balance > 0 {
+            // Burning a frozen balance would let the account escape the freeze
+            self.assert_not_frozen(&account_id);
+        }
+
+        self.update_account_snapshot(&account_id);
+        // Checkpoints are kept for past snapshots, so only the entries removed here are refunded
+        let initial_storage_usage = env::storage_usage();
+        self.accounts.remove(&account_id);
+        let deposit = self.storage_deposits.remove(&account_id);
+        if deposit.is_some() {
+            self.registered_accounts -= 1;
+        }
+        let storage_freed = initial_storage_usage.saturating_sub(env::storage_usage());
+        let freed_cost = env::storage_byte_cost().as_yoctonear() * Balance::from(storage_freed);
+
+        if balance > 0 {
+            self.internal_set_total_supply(self.total_supply - balance);
+            self.emit_burn_event(&account_id, balance, Some("Force unregister".to_string()));
+        }
+
+        let refund = deposit.unwrap_or(0).min(freed_cost);
+        Promise::new(account_id).transfer(NearToken::from_yoctonear(refund + 1));
+        true
+    }
+
+    /// Registration covers the balance entry, the deposit record, the checkpoint count and the
+    /// checkpoint written until the next snapshot, which charges the owner for the ones after it
+    pub fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        let bytes_per_account = self.bytes_for_longest_account_id
+            + self.bytes_for_storage_deposit
+            + self.bytes_for_checkpoint_count
+            + self.bytes_for_checkpoint;
+        let required_storage_balance = Balance::from(bytes_per_account) * env::storage_byte_cost().as_yoctonear();
+        StorageBalanceBounds {
+            min: required_storage_balance.into(),
+            max: Some(required_storage_balance.into()),
+        }
+    }
+
+    pub fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
+        self.internal_storage_balance_of(&account_id)
+    }
+
+    fn internal_storage_balance_of(&self, account_id: &AccountId) -> Option<StorageBalance> {
+        self.storage_deposits.get(account_id).map(|total| StorageBalance {
+            total: total.into(),
+            available: 0.into(),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageBalance {
+    #[schemars(with = "String")]
+    pub total: U128,
+    #[schemars(with = "String")]
+    pub available: U128,
+}
+
+#[derive(Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageBalanceBounds {
+    #[schemars(with = "String")]
+    pub min: U128,
+    #[schemars(with = "String")]
+    pub max: Option<U128>,
+}
+
+// External contract interfaces
+#[near_sdk::ext_contract(ext_ft_receiver)]
+pub trait FungibleTokenReceiver {
+    fn ft_on_transfer(&mut self, sender_id: AccountId, amount: U128, msg: String) -> PromiseOrValue<U128>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::mock::MockAction;
+    use near_sdk::test_utils::{accounts, get_created_receipts, get_logs, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    fn get_context(predecessor_account_id: AccountId, attached_deposit: Balance) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .current_account_id(accounts(0))
+            .signer_account_id(predecessor_account_id.clone())
+            .predecessor_account_id(predecessor_account_id)
+            .attached_deposit(NearToken::from_yoctonear(attached_deposit));
+        builder
+    }
+
+    fn new_token(owner_id: AccountId, total_supply: Balance) -> UniteToken {
+        UniteToken::new(owner_id, total_supply.into(), None, None, None, None)
+    }
+
+    /// Registers `account_id` with the minimum storage deposit. Callers must
+    /// restore their own context afterwards.
+    fn register(contract: &mut UniteToken, account_id: AccountId) {
+        let deposit = contract.storage_balance_bounds().min.0;
+        testing_env!(get_context(account_id, deposit).build());
+        contract.storage_deposit(None, None);
+    }
+
+    #[test]
+    fn test_new() {
+        let mut context = get_context(accounts(1), 0);
+        testing_env!(context.build());
+
+        let total_supply = 1_000_000_000_000_000u128;
+        let contract = new_token(accounts(1), total_supply);
+        
+        assert_eq!(contract.ft_total_supply().0, total_supply);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, total_supply);
+        assert_eq!(contract.get_owner(), accounts(1));
+    }
+
+    #[test]
+    fn test_transfer() {
+        let mut context = get_context(accounts(2), 1);
+        testing_env!(context.build());
+
+        let total_supply = 1_000_000_000_000_000u128;
+        let mut contract = new_token(accounts(2), total_supply);
+        let transfer_amount = 1_000_000_000_000u128;
+        register(&mut contract, accounts(1));
+        testing_env!(context.build());
+
+        contract.ft_transfer(accounts(1), transfer_amount.into(), None);
+        
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, total_supply - transfer_amount);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, transfer_amount);
+    }
+
+    #[test]
+    fn test_mint() {
+        let mut context = get_context(accounts(1), 1);
+        testing_env!(context.build());
+
+        let total_supply = 1_000_000_000_000_000u128;
+        let mut contract = new_token(accounts(1), total_supply);
+        let mint_amount = 1_000_000_000_000u128;
+        register(&mut contract, accounts(2));
+        testing_env!(context.build());
+
+        contract.mint(accounts(2), mint_amount.into());
+        
+        assert_eq!(contract.ft_total_supply().0, total_supply + mint_amount);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, mint_amount);
+    }
+
+    #[test]
     fn test_burn() {
         let mut context = get_context(accounts(1), 1);
         testing_env!(context.build());
 
         let total_supply = 1_000_000_000_000_000u128;
-        let mut contract = UniteToken::new(accounts(1), total_supply.into(), None);
+        let mut contract = new_token(accounts(1), total_supply);
         let burn_amount = 1_000_000_000_000u128;
 
         contract.burn(burn_amount.into());
@@ -446,24 +1373,1383 @@ mod tests {
         testing_env!(context.build());
 
         let total_supply = 1_000u128;
-        let mut contract = UniteToken::new(accounts(1), total_supply.into(), None);
+        let mut contract = new_token(accounts(1), total_supply);
 
         contract.ft_transfer(accounts(2), (total_supply + 1).into(), None);
     }
 
     #[test]
-    fn test_mint_by_anyone() {
+    fn test_mint_by_anyone_in_faucet_mode() {
         let mut context = get_context(accounts(2), 1);
         testing_env!(context.build());
 
         let total_supply = 1_000_000_000_000_000u128;
-        let mut contract = UniteToken::new(accounts(1), total_supply.into(), None);
+        let mut contract = UniteToken::new(accounts(1), total_supply.into(), None, None, Some(true), None);
         let mint_amount = 1000u128;
+        register(&mut contract, accounts(2));
+        testing_env!(context.build());
 
-        // Non-owner can mint tokens
+        // Non-owner can mint tokens while faucet mode is on
         contract.mint(accounts(2), mint_amount.into());
         
         assert_eq!(contract.ft_total_supply().0, total_supply + mint_amount);
         assert_eq!(contract.ft_balance_of(accounts(2)).0, mint_amount);
     }
-}
\ No newline at end of file
+
+    #[test]
+    #[should_panic(expected = "Only owner or minters can mint")]
+    fn test_mint_by_non_minter() {
+        let mut context = get_context(accounts(2), 1);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+
+        contract.mint(accounts(2), 1_000u128.into());
+    }
+
+    #[test]
+    fn test_mint_by_minter() {
+        let mut context = get_context(accounts(1), 1);
+        testing_env!(context.build());
+
+        let total_supply = 1_000u128;
+        let mut contract = new_token(accounts(1), total_supply);
+        contract.add_minter(accounts(2));
+        assert!(contract.is_minter(accounts(2)));
+        assert_eq!(contract.get_minters(), vec![accounts(2)]);
+        register(&mut contract, accounts(3));
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.mint(accounts(3), 500u128.into());
+
+        assert_eq!(contract.ft_total_supply().0, total_supply + 500);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 500);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only owner or minters can mint")]
+    fn test_mint_by_removed_minter() {
+        let mut context = get_context(accounts(1), 1);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+        contract.add_minter(accounts(2));
+        contract.remove_minter(accounts(2));
+        assert!(!contract.is_minter(accounts(2)));
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.mint(accounts(3), 500u128.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Only owner can call this method")]
+    fn test_add_minter_by_non_owner() {
+        let mut context = get_context(accounts(2), 1);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+
+        contract.add_minter(accounts(2));
+    }
+
+    #[test]
+    fn test_mint_up_to_max_supply() {
+        let mut context = get_context(accounts(1), 1);
+        testing_env!(context.build());
+
+        let mut contract = UniteToken::new(accounts(1), 1_000u128.into(), None, Some(1_500u128.into()), None, None);
+        register(&mut contract, accounts(2));
+        testing_env!(context.build());
+
+        contract.mint(accounts(2), 500u128.into());
+
+        assert_eq!(contract.ft_total_supply().0, 1_500);
+        assert_eq!(contract.get_max_supply(), Some(1_500u128.into()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Max supply exceeded")]
+    fn test_mint_above_max_supply() {
+        let mut context = get_context(accounts(1), 1);
+        testing_env!(context.build());
+
+        let mut contract = UniteToken::new(accounts(1), 1_000u128.into(), None, Some(1_500u128.into()), None, None);
+        register(&mut contract, accounts(2));
+        testing_env!(context.build());
+
+        contract.mint(accounts(2), 501u128.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Initial supply exceeds max supply")]
+    fn test_new_initial_supply_above_max_supply() {
+        let context = get_context(accounts(1), 0);
+        testing_env!(context.build());
+
+        UniteToken::new(accounts(1), 2_000u128.into(), None, Some(1_500u128.into()), None, None);
+    }
+
+    #[test]
+    fn test_storage_deposit_registers_account() {
+        let context = get_context(accounts(1), 0);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+        assert!(contract.storage_balance_of(accounts(2)).is_none());
+
+        register(&mut contract, accounts(2));
+
+        let balance = contract.storage_balance_of(accounts(2)).unwrap();
+        assert_eq!(balance.total, contract.storage_balance_bounds().min);
+        assert_eq!(balance.available.0, 0);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 0);
+    }
+
+    #[test]
+    fn test_storage_deposit_for_other_account() {
+        let context = get_context(accounts(1), 0);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+        let deposit = contract.storage_balance_bounds().min.0;
+        testing_env!(get_context(accounts(1), deposit * 2).build());
+
+        contract.storage_deposit(Some(accounts(3)), None);
+
+        assert!(contract.storage_balance_of(accounts(3)).is_some());
+    }
+
+    #[test]
+    #[should_panic(expected = "The attached deposit is less than the minimum storage balance")]
+    fn test_storage_deposit_insufficient() {
+        let context = get_context(accounts(1), 0);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+        let deposit = contract.storage_balance_bounds().min.0;
+        testing_env!(get_context(accounts(2), deposit - 1).build());
+
+        contract.storage_deposit(None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "The account charlie is not registered")]
+    fn test_transfer_to_unregistered_account() {
+        let context = get_context(accounts(1), 1);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+
+        contract.ft_transfer(accounts(2), 100u128.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "The account charlie is not registered")]
+    fn test_mint_to_unregistered_account() {
+        let context = get_context(accounts(1), 1);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+
+        contract.mint(accounts(2), 100u128.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "The amount is greater than the available storage balance")]
+    fn test_storage_withdraw_more_than_available() {
+        let context = get_context(accounts(1), 1);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+
+        contract.storage_withdraw(Some(1u128.into()));
+    }
+
+    #[test]
+    fn test_storage_unregister_empty_account() {
+        let context = get_context(accounts(2), 1);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+        register(&mut contract, accounts(2));
+        testing_env!(context.build());
+
+        assert!(contract.storage_unregister(None));
+        assert!(contract.storage_balance_of(accounts(2)).is_none());
+        assert!(!contract.storage_unregister(None));
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't unregister the account with the positive balance without force")]
+    fn test_storage_unregister_with_balance() {
+        let context = get_context(accounts(1), 1);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+
+        contract.storage_unregister(None);
+    }
+
+    #[test]
+    fn test_storage_unregister_force_burns_balance() {
+        let context = get_context(accounts(1), 1);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+        register(&mut contract, accounts(2));
+        testing_env!(context.build());
+        contract.ft_transfer(accounts(2), 400u128.into(), None);
+
+        assert!(contract.storage_unregister(Some(true)));
+
+        assert!(contract.storage_balance_of(accounts(1)).is_none());
+        assert_eq!(contract.ft_total_supply().0, 400);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 0);
+    }
+
+    #[test]
+    fn test_storage_unregister_refunds_paid_deposit() {
+        let context = get_context(accounts(1), 1);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+        let min_balance = contract.storage_balance_bounds().min.0;
+        testing_env!(get_context(accounts(2), min_balance).build());
+        let initial_storage_usage = env::storage_usage();
+        contract.storage_deposit(None, None);
+        let storage_used = env::storage_usage() - initial_storage_usage;
+
+        testing_env!(get_context(accounts(2), 1).build());
+        assert!(contract.storage_unregister(None));
+
+        // Only the bytes the shorter account ID actually freed are refunded
+        let freed_cost = env::storage_byte_cost().as_yoctonear() * Balance::from(storage_used);
+        assert!(freed_cost < min_balance);
+        assert_eq!(transfers_to(accounts(2)), vec![freed_cost + 1]);
+    }
+
+    #[test]
+    fn test_storage_unregister_migrated_holder_refunds_nothing() {
+        let context = get_context(accounts(0), 0);
+        testing_env!(context.build());
+        write_v1_state(accounts(1), &[(accounts(1), 700), (accounts(2), 300)]);
+        let mut contract = UniteToken::migrate();
+
+        testing_env!(get_context(accounts(2), 1).build());
+        assert!(contract.storage_unregister(Some(true)));
+
+        assert_eq!(transfers_to(accounts(2)), vec![1]);
+        assert_eq!(contract.ft_total_supply().0, 700);
+        assert_eq!(contract.registered_accounts, 0);
+    }
+
+    #[test]
+    fn test_storage_unregister_owner_refunds_nothing() {
+        let context = get_context(accounts(1), 1);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 0u128);
+        testing_env!(context.build());
+        assert!(contract.storage_unregister(None));
+
+        assert_eq!(transfers_to(accounts(1)), vec![1]);
+        assert_eq!(contract.registered_accounts, 0);
+    }
+
+    #[test]
+    fn test_new_emits_mint_event() {
+        let context = get_context(accounts(1), 0);
+        testing_env!(context.build());
+
+        new_token(accounts(1), 1_000u128);
+
+        assert_eq!(
+            get_logs(),
+            vec![r#"EVENT_JSON:{"standard":"nep141","version":"1.0.0","event":"ft_mint","data":[{"owner_id":"bob","amount":"1000","memo":"Initial supply"}]}"#]
+        );
+    }
+
+    #[test]
+    fn test_transfer_emits_event() {
+        let context = get_context(accounts(1), 1);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+        register(&mut contract, accounts(2));
+        testing_env!(context.build());
+
+        contract.ft_transfer(accounts(2), 100u128.into(), Some("hi".to_string()));
+        contract.ft_transfer(accounts(2), 50u128.into(), None);
+
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"nep141","version":"1.0.0","event":"ft_transfer","data":[{"old_owner_id":"bob","new_owner_id":"charlie","amount":"100","memo":"hi"}]}"#,
+                r#"EVENT_JSON:{"standard":"nep141","version":"1.0.0","event":"ft_transfer","data":[{"old_owner_id":"bob","new_owner_id":"charlie","amount":"50"}]}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_burn_emits_event() {
+        let context = get_context(accounts(1), 1);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+        testing_env!(context.build());
+
+        contract.burn(10u128.into());
+
+        assert_eq!(
+            get_logs(),
+            vec![r#"EVENT_JSON:{"standard":"nep141","version":"1.0.0","event":"ft_burn","data":[{"owner_id":"bob","amount":"10"}]}"#]
+        );
+    }
+
+    #[test]
+    fn test_batched_events() {
+        let context = get_context(accounts(1), 0);
+        testing_env!(context.build());
+
+        FtMintEvent::emit_many(&[
+            FtMintEvent { owner_id: accounts(1), amount: 1u128.into(), memo: None },
+            FtMintEvent { owner_id: accounts(2), amount: 2u128.into(), memo: Some("two".to_string()) },
+        ]);
+        FtTransferEvent::emit_many(&[
+            FtTransferEvent { old_owner_id: accounts(1), new_owner_id: accounts(2), amount: 3u128.into(), memo: None },
+            FtTransferEvent { old_owner_id: accounts(2), new_owner_id: accounts(3), amount: 4u128.into(), memo: None },
+        ]);
+
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"nep141","version":"1.0.0","event":"ft_mint","data":[{"owner_id":"bob","amount":"1"},{"owner_id":"charlie","amount":"2","memo":"two"}]}"#,
+                r#"EVENT_JSON:{"standard":"nep141","version":"1.0.0","event":"ft_transfer","data":[{"old_owner_id":"bob","new_owner_id":"charlie","amount":"3"},{"old_owner_id":"charlie","new_owner_id":"danny","amount":"4"}]}"#,
+            ]
+        );
+    }
+
+    /// Sets up `sender` (owner, 1_000) and `receiver` as if `ft_transfer_call` moved
+    /// `amount` to the receiver, then installs `result` as the receiver's promise result.
+    fn resolve_setup(amount: Balance, result: PromiseResult) -> UniteToken {
+        let context = get_context(accounts(1), 1);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+        register(&mut contract, accounts(2));
+        testing_env!(context.build());
+        contract.ft_transfer(accounts(2), amount.into(), None);
+
+        set_promise_result(result);
+        contract
+    }
+
+    /// Runs the next call as the contract itself, with `result` as promise 0.
+    fn set_promise_result(result: PromiseResult) {
+        testing_env!(
+            get_context(accounts(0), 0).build(),
+            near_sdk::test_vm_config(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![result],
+        );
+    }
+
+    fn unused(amount: Balance) -> PromiseResult {
+        PromiseResult::Successful(near_sdk::serde_json::to_vec(&U128(amount)).unwrap())
+    }
+
+    #[test]
+    fn test_resolve_transfer_all_used() {
+        let mut contract = resolve_setup(100, unused(0));
+
+        let used = contract.ft_resolve_transfer(accounts(1), accounts(2), 100u128.into());
+
+        assert_eq!(used.0, 100);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 900);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 100);
+        assert!(get_logs().is_empty());
+    }
+
+    #[test]
+    fn test_resolve_transfer_partial_refund() {
+        let mut contract = resolve_setup(100, unused(30));
+
+        let used = contract.ft_resolve_transfer(accounts(1), accounts(2), 100u128.into());
+
+        assert_eq!(used.0, 70);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 930);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 70);
+        assert_eq!(
+            get_logs(),
+            vec![r#"EVENT_JSON:{"standard":"nep141","version":"1.0.0","event":"ft_transfer","data":[{"old_owner_id":"charlie","new_owner_id":"bob","amount":"30","memo":"Refund"}]}"#]
+        );
+    }
+
+    #[test]
+    fn test_resolve_transfer_unused_capped_at_amount() {
+        let mut contract = resolve_setup(100, unused(500));
+
+        let used = contract.ft_resolve_transfer(accounts(1), accounts(2), 100u128.into());
+
+        assert_eq!(used.0, 0);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 1_000);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 0);
+    }
+
+    #[test]
+    fn test_resolve_transfer_failed_promise_refunds_all() {
+        let mut contract = resolve_setup(100, PromiseResult::Failed);
+
+        let used = contract.ft_resolve_transfer(accounts(1), accounts(2), 100u128.into());
+
+        assert_eq!(used.0, 0);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 1_000);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 0);
+    }
+
+    #[test]
+    fn test_resolve_transfer_invalid_result_refunds_all() {
+        let mut contract = resolve_setup(100, PromiseResult::Successful(b"not a number".to_vec()));
+
+        let used = contract.ft_resolve_transfer(accounts(1), accounts(2), 100u128.into());
+
+        assert_eq!(used.0, 0);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 1_000);
+    }
+
+    #[test]
+    fn test_resolve_transfer_receiver_spent_tokens() {
+        let mut contract = resolve_setup(100, unused(100));
+        // Receiver forwards 60 back to the owner before the callback runs
+        testing_env!(get_context(accounts(2), 1).build());
+        contract.ft_transfer(accounts(1), 60u128.into(), None);
+        set_promise_result(unused(100));
+
+        let used = contract.ft_resolve_transfer(accounts(1), accounts(2), 100u128.into());
+
+        assert_eq!(used.0, 60);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 1_000);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 0);
+    }
+
+    #[test]
+    fn test_resolve_transfer_receiver_deleted() {
+        let mut contract = resolve_setup(100, unused(100));
+        testing_env!(get_context(accounts(2), 1).build());
+        contract.storage_unregister(Some(true));
+        set_promise_result(unused(100));
+
+        let used = contract.ft_resolve_transfer(accounts(1), accounts(2), 100u128.into());
+
+        assert_eq!(used.0, 100);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 900);
+        assert!(contract.storage_balance_of(accounts(2)).is_none());
+    }
+
+    #[test]
+    fn test_resolve_transfer_sender_deleted_burns_refund() {
+        let mut contract = resolve_setup(100, unused(30));
+        // Sender force-unregisters while the receiver call is in flight
+        testing_env!(get_context(accounts(1), 1).build());
+        contract.storage_unregister(Some(true));
+        set_promise_result(unused(30));
+
+        let used = contract.ft_resolve_transfer(accounts(1), accounts(2), 100u128.into());
+
+        assert_eq!(used.0, 100);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 70);
+        assert_eq!(contract.ft_total_supply().0, 70);
+        assert!(contract.storage_balance_of(accounts(1)).is_none());
+        assert_eq!(
+            get_logs(),
+            vec![
+                "The account of the sender was deleted",
+                r#"EVENT_JSON:{"standard":"nep141","version":"1.0.0","event":"ft_burn","data":[{"owner_id":"charlie","amount":"30","memo":"Refund"}]}"#,
+            ]
+        );
+    }
+
+    const APPROVE_DEPOSIT: Balance = 10_000_000_000_000_000_000_000;
+
+    #[test]
+    fn test_approve_and_allowance() {
+        let context = get_context(accounts(1), APPROVE_DEPOSIT);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+        testing_env!(context.build());
+        contract.ft_approve(accounts(2), 300u128.into());
+
+        assert_eq!(contract.ft_allowance(accounts(1), accounts(2)).0, 300);
+        assert_eq!(contract.ft_allowance(accounts(2), accounts(1)).0, 0);
+        assert_eq!(
+            get_logs(),
+            vec![r#"EVENT_JSON:{"standard":"unite","version":"1.0.0","event":"ft_approve","data":[{"owner_id":"bob","spender_id":"charlie","amount":"300"}]}"#]
+        );
+
+        contract.ft_approve(accounts(2), 0u128.into());
+        assert_eq!(contract.ft_allowance(accounts(1), accounts(2)).0, 0);
+    }
+
+    /// Storage cost of a new allowance, measured by approving `spender_id` in the current context
+    fn approve_measuring_cost(contract: &mut UniteToken, spender_id: AccountId, amount: Balance) -> Balance {
+        let initial_storage_usage = env::storage_usage();
+        contract.ft_approve(spender_id, amount.into());
+        env::storage_byte_cost().as_yoctonear() * Balance::from(env::storage_usage() - initial_storage_usage)
+    }
+
+    /// Amounts transferred to `account_id` by the receipts created in the current context
+    fn transfers_to(account_id: AccountId) -> Vec<Balance> {
+        get_created_receipts()
+            .into_iter()
+            .filter(|receipt| receipt.receiver_id == account_id)
+            .flat_map(|receipt| receipt.actions)
+            .filter_map(|action| match action {
+                MockAction::Transfer { deposit, .. } => Some(deposit.as_yoctonear()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_approve_charges_owner_for_storage() {
+        let context = get_context(accounts(1), APPROVE_DEPOSIT);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+        let allowance_cost = approve_measuring_cost(&mut contract, accounts(2), 300);
+
+        assert!(allowance_cost > 0);
+        assert_eq!(transfers_to(accounts(1)), vec![APPROVE_DEPOSIT - allowance_cost]);
+    }
+
+    #[test]
+    fn test_approve_zero_refunds_storage() {
+        let context = get_context(accounts(1), APPROVE_DEPOSIT);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+        let allowance_cost = approve_measuring_cost(&mut contract, accounts(2), 300);
+
+        testing_env!(get_context(accounts(1), 1).build());
+        contract.ft_approve(accounts(2), 0u128.into());
+
+        assert_eq!(transfers_to(accounts(1)), vec![1 + allowance_cost]);
+    }
+
+    #[test]
+    fn test_transfer_from_full_allowance_refunds_owner_storage() {
+        let context = get_context(accounts(1), APPROVE_DEPOSIT);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+        register(&mut contract, accounts(3));
+        testing_env!(context.build());
+        let allowance_cost = approve_measuring_cost(&mut contract, accounts(2), 300);
+
+        testing_env!(get_context(accounts(2), 1).build());
+        contract.ft_transfer_from(accounts(1), accounts(3), 300u128.into(), None);
+
+        assert_eq!(contract.ft_allowance(accounts(1), accounts(2)).0, 0);
+        assert_eq!(transfers_to(accounts(1)), vec![allowance_cost]);
+        assert!(transfers_to(accounts(2)).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "to cover storage")]
+    fn test_approve_without_storage_deposit() {
+        let context = get_context(accounts(1), 1);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+
+        contract.ft_approve(accounts(2), 300u128.into());
+    }
+
+    #[test]
+    fn test_transfer_from() {
+        let context = get_context(accounts(1), APPROVE_DEPOSIT);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+        register(&mut contract, accounts(3));
+        testing_env!(context.build());
+        contract.ft_approve(accounts(2), 300u128.into());
+
+        testing_env!(get_context(accounts(2), 1).build());
+        contract.ft_transfer_from(accounts(1), accounts(3), 200u128.into(), None);
+
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 800);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 200);
+        assert_eq!(contract.ft_allowance(accounts(1), accounts(2)).0, 100);
+        assert_eq!(
+            get_logs(),
+            vec![r#"EVENT_JSON:{"standard":"nep141","version":"1.0.0","event":"ft_transfer","data":[{"old_owner_id":"bob","new_owner_id":"danny","amount":"200"}]}"#]
+        );
+
+        contract.ft_transfer_from(accounts(1), accounts(3), 100u128.into(), None);
+        assert_eq!(contract.ft_allowance(accounts(1), accounts(2)).0, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Insufficient allowance")]
+    fn test_transfer_from_above_allowance() {
+        let context = get_context(accounts(1), APPROVE_DEPOSIT);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+        register(&mut contract, accounts(3));
+        testing_env!(context.build());
+        contract.ft_approve(accounts(2), 300u128.into());
+
+        testing_env!(get_context(accounts(2), 1).build());
+        contract.ft_transfer_from(accounts(1), accounts(3), 301u128.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Insufficient allowance")]
+    fn test_transfer_from_without_approval() {
+        let context = get_context(accounts(2), 1);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+
+        contract.ft_transfer_from(accounts(1), accounts(2), 1u128.into(), None);
+    }
+
+    fn reference_metadata() -> FungibleTokenMetadata {
+        let reference_hash = near_sdk::serde_json::to_value(Base64VecU8::from(env::sha256(b"{}")))
+            .unwrap()
+            .as_str()
+            .unwrap()
+            .to_string();
+        FungibleTokenMetadata {
+            name: "UNITE V2".to_string(),
+            symbol: "UNT".to_string(),
+            icon: Some("data:image/svg+xml,<svg/>".to_string()),
+            reference: Some("https://example.com/unite.json".to_string()),
+            reference_hash: Some(reference_hash),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_set_metadata() {
+        let context = get_context(accounts(1), APPROVE_DEPOSIT);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+        testing_env!(context.build());
+        let metadata = reference_metadata();
+        contract.set_metadata(metadata.clone());
+
+        let stored = contract.ft_metadata();
+        assert_eq!(stored.name, "UNITE V2");
+        assert_eq!(stored.symbol, "UNT");
+        assert_eq!(stored.icon, metadata.icon);
+        assert_eq!(stored.reference_hash, metadata.reference_hash);
+        assert_eq!(
+            get_logs(),
+            vec![format!(
+                r#"EVENT_JSON:{{"standard":"unite","version":"1.0.0","event":"ft_metadata_update","data":[{{"updated_by":"bob","name":"UNITE V2","symbol":"UNT","icon_changed":true,"reference":"https://example.com/unite.json","reference_hash":"{}"}}]}}"#,
+                metadata.reference_hash.unwrap()
+            )]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "to cover storage")]
+    fn test_set_metadata_larger_icon_without_storage_deposit() {
+        let context = get_context(accounts(1), 1);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+
+        contract.set_metadata(FungibleTokenMetadata {
+            icon: Some(format!("data:image/svg+xml,{}", "a".repeat(1_000))),
+            ..Default::default()
+        });
+    }
+
+    #[test]
+    fn test_set_metadata_smaller_refunds_storage() {
+        let context = get_context(accounts(1), APPROVE_DEPOSIT);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+        contract.set_metadata(reference_metadata());
+
+        testing_env!(get_context(accounts(1), 1).build());
+        let freed_bytes = borsh::to_vec(&reference_metadata()).unwrap().len()
+            - borsh::to_vec(&FungibleTokenMetadata::default()).unwrap().len();
+        contract.set_metadata(FungibleTokenMetadata::default());
+
+        let freed_cost = env::storage_byte_cost().as_yoctonear() * freed_bytes as Balance;
+        assert_eq!(transfers_to(accounts(1)), vec![1 + freed_cost]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only owner can call this method")]
+    fn test_set_metadata_by_non_owner() {
+        let context = get_context(accounts(1), 0);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+        testing_env!(get_context(accounts(2), APPROVE_DEPOSIT).build());
+
+        contract.set_metadata(reference_metadata());
+    }
+
+    #[test]
+    #[should_panic(expected = "Decimals cannot be changed")]
+    fn test_set_metadata_changes_decimals() {
+        let context = get_context(accounts(1), APPROVE_DEPOSIT);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+
+        contract.set_metadata(FungibleTokenMetadata { decimals: 18, ..reference_metadata() });
+    }
+
+    #[test]
+    #[should_panic(expected = "Unsupported metadata spec")]
+    fn test_set_metadata_unsupported_spec() {
+        let context = get_context(accounts(1), APPROVE_DEPOSIT);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+
+        contract.set_metadata(FungibleTokenMetadata { spec: "ft-2.0.0".to_string(), ..reference_metadata() });
+    }
+
+    #[test]
+    #[should_panic(expected = "Reference hash must be a base64-encoded sha256 hash")]
+    fn test_set_metadata_short_reference_hash() {
+        let context = get_context(accounts(1), APPROVE_DEPOSIT);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+
+        contract.set_metadata(FungibleTokenMetadata {
+            reference_hash: Some("AAAA".to_string()),
+            ..reference_metadata()
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Reference hash must be a base64-encoded sha256 hash")]
+    fn test_set_metadata_invalid_base64_reference_hash() {
+        let context = get_context(accounts(1), APPROVE_DEPOSIT);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+
+        contract.set_metadata(FungibleTokenMetadata {
+            reference_hash: Some("not base64!".to_string()),
+            ..reference_metadata()
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Reference and reference hash must be set together")]
+    fn test_set_metadata_reference_without_hash() {
+        let context = get_context(accounts(1), APPROVE_DEPOSIT);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+
+        contract.set_metadata(FungibleTokenMetadata { reference_hash: None, ..reference_metadata() });
+    }
+
+    #[test]
+    #[should_panic(expected = "Icon must be a data URL")]
+    fn test_set_metadata_icon_not_data_url() {
+        let context = get_context(accounts(1), APPROVE_DEPOSIT);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+
+        contract.set_metadata(FungibleTokenMetadata {
+            icon: Some("https://example.com/icon.png".to_string()),
+            ..reference_metadata()
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Icon data URL is too large")]
+    fn test_set_metadata_icon_too_large() {
+        let context = get_context(accounts(1), APPROVE_DEPOSIT);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+
+        contract.set_metadata(FungibleTokenMetadata {
+            icon: Some(format!("data:image/svg+xml,{}", "a".repeat(MAX_ICON_LENGTH))),
+            ..reference_metadata()
+        });
+    }
+
+    /// Compliance-enabled token owned by `bob` with `charlie` registered and holding 100
+    fn compliance_setup() -> UniteToken {
+        let context = get_context(accounts(1), 1);
+        testing_env!(context.build());
+
+        let mut contract = UniteToken::new(accounts(1), 1_000u128.into(), None, None, None, Some(true));
+        register(&mut contract, accounts(2));
+        testing_env!(context.build());
+        contract.ft_transfer(accounts(2), 100u128.into(), None);
+        contract
+    }
+
+    #[test]
+    fn test_freeze_and_unfreeze_account() {
+        let mut contract = compliance_setup();
+
+        contract.freeze_account(accounts(2));
+        assert!(contract.is_frozen(accounts(2)));
+        contract.unfreeze_account(accounts(2));
+        assert!(!contract.is_frozen(accounts(2)));
+
+        let logs = get_logs();
+        assert_eq!(
+            logs[logs.len() - 2..],
+            [
+                r#"EVENT_JSON:{"standard":"unite","version":"1.0.0","event":"account_frozen","data":[{"account_id":"charlie"}]}"#,
+                r#"EVENT_JSON:{"standard":"unite","version":"1.0.0","event":"account_unfrozen","data":[{"account_id":"charlie"}]}"#,
+            ]
+        );
+
+        testing_env!(get_context(accounts(2), 1).build());
+        contract.ft_transfer(accounts(1), 10u128.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 90);
+    }
+
+    #[test]
+    #[should_panic(expected = "The account charlie is frozen")]
+    fn test_frozen_account_cannot_send() {
+        let mut contract = compliance_setup();
+        contract.freeze_account(accounts(2));
+
+        testing_env!(get_context(accounts(2), 1).build());
+        contract.ft_transfer(accounts(1), 10u128.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "The account charlie is frozen")]
+    fn test_frozen_account_cannot_receive() {
+        let mut contract = compliance_setup();
+        contract.freeze_account(accounts(2));
+
+        contract.ft_transfer(accounts(2), 10u128.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "The account charlie is frozen")]
+    fn test_frozen_account_cannot_burn() {
+        let mut contract = compliance_setup();
+        contract.freeze_account(accounts(2));
+
+        testing_env!(get_context(accounts(2), 1).build());
+        contract.burn(10u128.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "The account charlie is frozen")]
+    fn test_frozen_account_cannot_transfer_call() {
+        let mut contract = compliance_setup();
+        contract.freeze_account(accounts(2));
+
+        testing_env!(get_context(accounts(2), 1).build());
+        contract.ft_transfer_call(accounts(1), 10u128.into(), None, String::new());
+    }
+
+    #[test]
+    #[should_panic(expected = "The account charlie is frozen")]
+    fn test_frozen_account_cannot_force_unregister() {
+        let mut contract = compliance_setup();
+        contract.freeze_account(accounts(2));
+
+        testing_env!(get_context(accounts(2), 1).build());
+        contract.storage_unregister(Some(true));
+    }
+
+    #[test]
+    #[should_panic(expected = "Compliance module is disabled")]
+    fn test_freeze_when_compliance_disabled() {
+        let context = get_context(accounts(1), 1);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+
+        contract.freeze_account(accounts(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only owner can call this method")]
+    fn test_freeze_by_non_owner() {
+        let mut contract = compliance_setup();
+
+        testing_env!(get_context(accounts(2), 1).build());
+        contract.freeze_account(accounts(1));
+    }
+
+    #[test]
+    fn test_force_transfer_from_frozen_account() {
+        let mut contract = compliance_setup();
+        contract.freeze_account(accounts(2));
+        testing_env!(get_context(accounts(1), 1).build());
+
+        contract.force_transfer(accounts(2), accounts(1), 60u128.into(), Some("court order".to_string()));
+
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 960);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 40);
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"nep141","version":"1.0.0","event":"ft_transfer","data":[{"old_owner_id":"charlie","new_owner_id":"bob","amount":"60","memo":"court order"}]}"#,
+                r#"EVENT_JSON:{"standard":"unite","version":"1.0.0","event":"force_transfer","data":[{"executed_by":"bob","old_owner_id":"charlie","new_owner_id":"bob","amount":"60","memo":"court order"}]}"#,
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Only owner can call this method")]
+    fn test_force_transfer_by_non_owner() {
+        let mut contract = compliance_setup();
+
+        testing_env!(get_context(accounts(2), 1).build());
+        contract.force_transfer(accounts(1), accounts(2), 10u128.into(), None);
+    }
+
+    const SNAPSHOT_DEPOSIT: Balance = 100_000_000_000_000_000_000_000;
+
+    /// Takes a snapshot as `bob`, leaving `bob`'s 1 yoctoNEAR context in place
+    fn take_snapshot(contract: &mut UniteToken) -> u64 {
+        testing_env!(get_context(accounts(1), SNAPSHOT_DEPOSIT).build());
+        let snapshot_id = contract.snapshot();
+        testing_env!(get_context(accounts(1), 1).build());
+        snapshot_id
+    }
+
+    #[test]
+    fn test_snapshot_balances() {
+        let context = get_context(accounts(1), 1);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+        register(&mut contract, accounts(2));
+        testing_env!(context.build());
+
+        assert_eq!(take_snapshot(&mut contract), 1);
+        contract.ft_transfer(accounts(2), 100u128.into(), None);
+        contract.ft_transfer(accounts(2), 100u128.into(), None);
+        assert_eq!(take_snapshot(&mut contract), 2);
+        contract.ft_transfer(accounts(2), 300u128.into(), None);
+        assert_eq!(take_snapshot(&mut contract), 3);
+
+        assert_eq!(contract.ft_balance_of_at(accounts(1), 1).0, 1_000);
+        assert_eq!(contract.ft_balance_of_at(accounts(2), 1).0, 0);
+        assert_eq!(contract.ft_balance_of_at(accounts(1), 2).0, 800);
+        assert_eq!(contract.ft_balance_of_at(accounts(2), 2).0, 200);
+        // No changes since snapshot 3, so it reads the live balances
+        assert_eq!(contract.ft_balance_of_at(accounts(1), 3).0, 500);
+        assert_eq!(contract.ft_balance_of_at(accounts(2), 3).0, 500);
+        // Accounts that never held tokens
+        assert_eq!(contract.ft_balance_of_at(accounts(4), 1).0, 0);
+    }
+
+    #[test]
+    fn test_snapshot_balances_skip_unchanged_snapshots() {
+        let context = get_context(accounts(1), 1);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+        register(&mut contract, accounts(2));
+        testing_env!(context.build());
+
+        for snapshot_id in 1..=6 {
+            take_snapshot(&mut contract);
+            if snapshot_id % 3 == 0 {
+                contract.ft_transfer(accounts(2), 100u128.into(), None);
+            }
+        }
+
+        // Checkpoints were only written for snapshots 3 and 6
+        assert_eq!(contract.account_snapshot_counts.get(&accounts(1)), Some(2));
+        assert_eq!(contract.ft_balance_of_at(accounts(1), 1).0, 1_000);
+        assert_eq!(contract.ft_balance_of_at(accounts(1), 3).0, 1_000);
+        assert_eq!(contract.ft_balance_of_at(accounts(1), 4).0, 900);
+        assert_eq!(contract.ft_balance_of_at(accounts(1), 6).0, 900);
+        assert_eq!(contract.ft_balance_of_at(accounts(2), 5).0, 100);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 800);
+    }
+
+    #[test]
+    fn test_snapshot_total_supply() {
+        let context = get_context(accounts(1), 1);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+
+        take_snapshot(&mut contract);
+        contract.mint(accounts(1), 500u128.into());
+        take_snapshot(&mut contract);
+        take_snapshot(&mut contract);
+        contract.burn(200u128.into());
+
+        assert_eq!(contract.ft_total_supply_at(1).0, 1_000);
+        assert_eq!(contract.ft_total_supply_at(2).0, 1_500);
+        assert_eq!(contract.ft_total_supply_at(3).0, 1_500);
+        assert_eq!(contract.ft_total_supply().0, 1_300);
+        assert_eq!(contract.ft_balance_of_at(accounts(1), 2).0, 1_500);
+    }
+
+    #[test]
+    fn test_snapshot_emits_event() {
+        let context = get_context(accounts(1), SNAPSHOT_DEPOSIT);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+        testing_env!(context.build());
+        contract.snapshot();
+
+        assert_eq!(
+            get_logs(),
+            vec![r#"EVENT_JSON:{"standard":"unite","version":"1.0.0","event":"snapshot","data":[{"snapshot_id":1}]}"#]
+        );
+    }
+
+    #[test]
+    fn test_snapshot_charges_a_checkpoint_per_registered_account() {
+        let context = get_context(accounts(1), 1);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+        register(&mut contract, accounts(2));
+        register(&mut contract, accounts(3));
+        testing_env!(get_context(accounts(1), SNAPSHOT_DEPOSIT).build());
+        contract.snapshot();
+
+        // Three registered accounts and the total supply
+        let storage_cost = env::storage_byte_cost().as_yoctonear() * Balance::from(contract.bytes_for_checkpoint) * 4;
+        assert_eq!(transfers_to(accounts(1)), vec![SNAPSHOT_DEPOSIT - storage_cost]);
+    }
+
+    #[test]
+    fn test_migrated_holder_registers_and_is_charged_for() {
+        let context = get_context(accounts(0), 0);
+        testing_env!(context.build());
+        write_v1_state(accounts(1), &[(accounts(1), 700), (accounts(2), 300)]);
+        let mut contract = UniteToken::migrate();
+        assert!(contract.storage_balance_of(accounts(2)).is_none());
+
+        let min_balance = contract.storage_balance_bounds().min.0;
+        testing_env!(get_context(accounts(2), min_balance).build());
+        contract.storage_deposit(None, None);
+
+        assert_eq!(contract.storage_balance_of(accounts(2)).unwrap().total.0, min_balance);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 300);
+
+        testing_env!(get_context(accounts(1), SNAPSHOT_DEPOSIT).build());
+        contract.snapshot();
+
+        // The registered holder and the total supply
+        let storage_cost = env::storage_byte_cost().as_yoctonear() * Balance::from(contract.bytes_for_checkpoint) * 2;
+        assert_eq!(transfers_to(accounts(1)), vec![SNAPSHOT_DEPOSIT - storage_cost]);
+    }
+
+    #[test]
+    #[should_panic(expected = "The account charlie must register with storage_deposit before its balance can change")]
+    fn test_unregistered_migrated_holder_cannot_write_checkpoint() {
+        let context = get_context(accounts(0), 0);
+        testing_env!(context.build());
+        write_v1_state(accounts(1), &[(accounts(1), 700), (accounts(2), 300)]);
+        let mut contract = UniteToken::migrate();
+        take_snapshot(&mut contract);
+
+        testing_env!(get_context(accounts(2), 1).build());
+        contract.ft_transfer(accounts(1), 100u128.into(), None);
+    }
+
+    #[test]
+    fn test_registration_covers_checkpoint_storage() {
+        let context = get_context(accounts(1), 1);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+        take_snapshot(&mut contract);
+        // Checkpoints the owner's balance, which the snapshot paid for
+        contract.burn(1u128.into());
+
+        let min_balance = contract.storage_balance_bounds().min.0;
+        testing_env!(get_context(accounts(2), min_balance).build());
+        let initial_storage_usage = env::storage_usage();
+        contract.storage_deposit(None, None);
+        let mut storage_used = env::storage_usage() - initial_storage_usage;
+
+        testing_env!(context.build());
+        let initial_storage_usage = env::storage_usage();
+        contract.ft_transfer(accounts(2), 100u128.into(), None);
+        storage_used += env::storage_usage() - initial_storage_usage;
+
+        assert_eq!(contract.account_snapshot_counts.get(&accounts(2)), Some(1));
+        assert!(env::storage_byte_cost().as_yoctonear() * Balance::from(storage_used) <= min_balance);
+    }
+
+    #[test]
+    #[should_panic(expected = "to cover storage")]
+    fn test_snapshot_without_storage_deposit() {
+        let context = get_context(accounts(1), 1);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+
+        contract.snapshot();
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid snapshot id")]
+    fn test_balance_of_at_future_snapshot() {
+        let context = get_context(accounts(1), 1);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+        take_snapshot(&mut contract);
+
+        contract.ft_balance_of_at(accounts(1), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid snapshot id")]
+    fn test_total_supply_at_zero_snapshot() {
+        let context = get_context(accounts(1), 1);
+        testing_env!(context.build());
+
+        let contract = new_token(accounts(1), 1_000u128);
+
+        contract.ft_total_supply_at(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only owner can call this method")]
+    fn test_snapshot_by_non_owner() {
+        let context = get_context(accounts(2), SNAPSHOT_DEPOSIT);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+
+        contract.snapshot();
+    }
+
+    /// Writes a version 1 state (as deployed before versioning) holding the given balances
+    fn write_v1_state(owner_id: AccountId, balances: &[(AccountId, Balance)]) {
+        let mut accounts = LookupMap::new(b"a");
+        let mut total_supply = 0;
+        for (account_id, balance) in balances {
+            accounts.insert(account_id, balance);
+            total_supply += balance;
+        }
+        env::state_write(&UniteTokenV1 {
+            accounts,
+            total_supply,
+            bytes_for_longest_account_id: 64,
+            metadata: FungibleTokenMetadata::default(),
+            owner_id,
+        });
+    }
+
+    #[test]
+    fn test_migrate_from_v1() {
+        let context = get_context(accounts(0), 0);
+        testing_env!(context.build());
+        write_v1_state(accounts(1), &[(accounts(1), 700), (accounts(2), 300)]);
+
+        let contract = UniteToken::migrate();
+
+        assert_eq!(contract.get_owner(), accounts(1));
+        assert_eq!(contract.ft_total_supply().0, 1_000);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 700);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 300);
+        assert_eq!(contract.ft_metadata().name, "UNITE V1");
+        assert!(contract.is_faucet_mode());
+        assert!(contract.get_minters().is_empty());
+        assert!(!contract.is_compliance_enabled());
+        assert_ne!(contract.bytes_for_longest_account_id, 64);
+        assert_eq!(
+            env::storage_read(STATE_VERSION_KEY),
+            Some(borsh::to_vec(&STATE_VERSION).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_migrated_v1_state_is_usable() {
+        let context = get_context(accounts(0), 0);
+        testing_env!(context.build());
+        write_v1_state(accounts(1), &[(accounts(1), 700), (accounts(2), 300)]);
+        let mut contract = UniteToken::migrate();
+
+        testing_env!(get_context(accounts(2), 1).build());
+        contract.ft_transfer(accounts(1), 100u128.into(), None);
+
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 800);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 200);
+    }
+
+    #[test]
+    fn test_set_faucet_mode_on_migrated_token() {
+        let context = get_context(accounts(0), 0);
+        testing_env!(context.build());
+        write_v1_state(accounts(1), &[(accounts(1), 700), (accounts(2), 300)]);
+        let mut contract = UniteToken::migrate();
+
+        testing_env!(get_context(accounts(1), 1).build());
+        contract.set_faucet_mode(false);
+
+        assert!(!contract.is_faucet_mode());
+        assert_eq!(
+            get_logs(),
+            vec![r#"EVENT_JSON:{"standard":"unite","version":"1.0.0","event":"faucet_mode_update","data":[{"updated_by":"bob","enabled":false}]}"#]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Only owner or minters can mint")]
+    fn test_migrated_token_closed_to_public_minting() {
+        let context = get_context(accounts(0), 0);
+        testing_env!(context.build());
+        write_v1_state(accounts(1), &[(accounts(1), 700), (accounts(2), 300)]);
+        let mut contract = UniteToken::migrate();
+        testing_env!(get_context(accounts(1), 1).build());
+        contract.set_faucet_mode(false);
+
+        testing_env!(get_context(accounts(2), 1).build());
+        contract.mint(accounts(2), 100u128.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Only owner can call this method")]
+    fn test_set_faucet_mode_by_non_owner() {
+        let context = get_context(accounts(2), 1);
+        testing_env!(context.build());
+
+        let mut contract = new_token(accounts(1), 1_000u128);
+
+        contract.set_faucet_mode(true);
+    }
+
+    #[test]
+    fn test_migrate_current_version_keeps_state() {
+        let context = get_context(accounts(1), 1);
+        testing_env!(context.build());
+        let mut contract = new_token(accounts(1), 1_000u128);
+        contract.add_minter(accounts(2));
+        env::state_write(&contract);
+
+        testing_env!(get_context(accounts(0), 0).build());
+        let contract = UniteToken::migrate();
+
+        assert_eq!(contract.ft_total_supply().0, 1_000);
+        assert_eq!(contract.get_minters(), vec![accounts(2)]);
+        assert!(!contract.is_faucet_mode());
+    }
+
+    #[test]
+    fn test_upgrade_by_owner() {
+        let mut context = get_context(accounts(1), 1);
+        testing_env!(context.build());
+        let contract = new_token(accounts(1), 1_000u128);
+
+        testing_env!(context.input(b"new code".to_vec()).build());
+        contract.upgrade();
+
+        let receipts = get_created_receipts();
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(receipts[0].receiver_id, accounts(0));
+        assert!(matches!(
+            &receipts[0].actions[..],
+            [
+                MockAction::DeployContract { code, .. },
+                MockAction::FunctionCallWeight { method_name, .. },
+            ] if code == b"new code" && method_name == b"migrate"
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only owner can call this method")]
+    fn test_upgrade_by_non_owner() {
+        let context = get_context(accounts(1), 1);
+        testing_env!(context.build());
+        let contract = new_token(accounts(1), 1_000u128);
+
+        testing_env!(get_context(accounts(2), 1).input(b"new code".to_vec()).build());
+        contract.upgrade();
+    }
+
+    #[test]
+    #[should_panic(expected = "Requires attached deposit of exactly 1 yoctoNEAR")]
+    fn test_upgrade_without_deposit() {
+        let mut context = get_context(accounts(1), 0);
+        testing_env!(context.build());
+        let contract = new_token(accounts(1), 1_000u128);
+
+        testing_env!(context.input(b"new code".to_vec()).build());
+        contract.upgrade();
+    }
+
+    /// Static gas attached to the scheduled call of `method`
+    fn scheduled_gas(method: &str) -> Gas {
+        get_created_receipts()
+            .iter()
+            .flat_map(|receipt| receipt.actions.iter())
+            .find_map(|action| match action {
+                MockAction::FunctionCallWeight { method_name, prepaid_gas, .. }
+                    if method_name == method.as_bytes() => Some(*prepaid_gas),
+                _ => None,
+            })
+            .unwrap_or_else(|| panic!("No call to {} was scheduled", method))
+    }
+
+    fn transfer_call_with_gas(prepaid_gas: Gas) {
+        let context = get_context(accounts(1), 1);
+        testing_env!(context.build());
+        let mut contract = new_token(accounts(1), 1_000u128);
+        register(&mut contract, accounts(2));
+
+        let mut context = get_context(accounts(1), 1);
+        testing_env!(context.prepaid_gas(prepaid_gas).build());
+        contract.ft_transfer_call(accounts(2), 100u128.into(), None, String::new());
+    }
+
+    #[test]
+    fn test_transfer_call_forwards_remaining_gas() {
+        let prepaid_gas = Gas::from_tgas(300);
+        transfer_call_with_gas(prepaid_gas);
+
+        let receiver_gas = scheduled_gas("ft_on_transfer");
+        let resolve_gas = scheduled_gas("ft_resolve_transfer");
+        let reserved = GAS_FOR_FT_TRANSFER_CALL.saturating_add(GAS_FOR_RESOLVE_TRANSFER);
+
+        assert_eq!(resolve_gas, GAS_FOR_RESOLVE_TRANSFER);
+        assert!(receiver_gas <= prepaid_gas.saturating_sub(reserved));
+        // Only the gas burnt by this call itself is missing
+        assert!(receiver_gas > prepaid_gas.saturating_sub(reserved).saturating_sub(Gas::from_tgas(10)));
+    }
+
+    #[test]
+    fn test_transfer_call_gas_scales_with_prepaid_gas() {
+        transfer_call_with_gas(Gas::from_tgas(100));
+        let low = scheduled_gas("ft_on_transfer");
+
+        transfer_call_with_gas(Gas::from_tgas(200));
+        let high = scheduled_gas("ft_on_transfer");
+
+        assert!(high.as_gas() - low.as_gas() >= Gas::from_tgas(99).as_gas());
+    }
+
+    #[test]
+    #[should_panic(expected = "More gas is required")]
+    fn test_transfer_call_not_enough_gas() {
+        transfer_call_with_gas(Gas::from_tgas(20));
+    }
+}
@@ -0,0 +1,186 @@
+#![no_std]
+use soroban_sdk::{
+    contract, contractclient, contractimpl, contracttype, contracterror, log, symbol_short, token, Address, BytesN, Env, String, Symbol
+};
+use soroban_sdk::xdr::ToXdr;
+
+const DAY_IN_LEDGERS: u32 = 17_280;
+
+// Instance storage (admin, escrow Wasm) is bumped back to 30 days once it drops below 29
+const INSTANCE_BUMP_AMOUNT: u32 = 30 * DAY_IN_LEDGERS;
+const INSTANCE_LIFETIME_THRESHOLD: u32 = INSTANCE_BUMP_AMOUNT - DAY_IN_LEDGERS;
+
+// Hash function the hashlock was built with, as the order escrow defines it
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HashAlgo {
+    Sha256,
+    Keccak256,
+}
+
+// Offsets from creation, in seconds, at which each finalization window opens, as the order
+// escrow defines them
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Timelocks {
+    pub withdrawal: u64,
+    pub public_withdrawal: u64,
+    pub cancellation: u64,
+    pub public_cancellation: u64,
+}
+
+// Order description passed through to the order escrow's `init`. The tests deploy the order
+// escrow's built Wasm, so a layout that drifts from it fails there.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EscrowParams {
+    pub order_id: String,
+    pub hash: BytesN<32>,
+    pub hash_algo: HashAlgo,
+    pub owner: Address,
+    pub taker: Address,
+    pub token_contract: Address,
+    pub amount: i128,
+    pub timelocks: Timelocks,
+}
+
+// Storage keys for instance storage
+#[contracttype]
+pub enum DataKey {
+    Admin,
+    EscrowWasm, // hash of the uploaded order escrow Wasm new escrows run
+}
+
+// Custom error types
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum Error {
+    AlreadyInitialized = 1,
+    NotInitialized = 2,
+}
+
+// Event types for logging, published under ("escrow", "deployed", hash, owner) topics
+#[contracttype]
+pub struct EscrowDeployedEvent {
+    pub order_id: String,
+    pub hash: BytesN<32>,
+    pub owner: Address,
+    pub taker: Address,
+    pub escrow: Address,
+    pub amount: i128,
+}
+
+// The part of the order escrow's interface the factory calls
+#[contractclient(name = "OrderEscrowClient")]
+pub trait OrderEscrowInterface {
+    fn init(env: Env, params: EscrowParams);
+}
+
+#[contract]
+pub struct EscrowFactoryContract;
+
+#[contractimpl]
+impl EscrowFactoryContract {
+    /// Sets the admin and the order escrow Wasm the factory deploys. Can only be called once.
+    pub fn initialize(env: Env, admin: Address, escrow_wasm_hash: BytesN<32>) -> Result<(), Error> {
+        if env.storage().instance().has(&DataKey::Admin) {
+            return Err(Error::AlreadyInitialized);
+        }
+        admin.require_auth();
+
+        env.storage().instance().set(&DataKey::Admin, &admin);
+        env.storage().instance().set(&DataKey::EscrowWasm, &escrow_wasm_hash);
+        Self::extend_instance_ttl(&env);
+        Ok(())
+    }
+
+    /// Switches the Wasm deployed for new orders, and so their addresses. Already deployed
+    /// escrows keep running their code.
+    pub fn set_escrow_wasm(env: Env, escrow_wasm_hash: BytesN<32>) -> Result<(), Error> {
+        let admin = Self::get_admin(env.clone())?;
+        admin.require_auth();
+        Self::extend_instance_ttl(&env);
+
+        env.storage().instance().set(&DataKey::EscrowWasm, &escrow_wasm_hash);
+        env.events().publish((Symbol::new(&env, "escrow_wasm_changed"),), escrow_wasm_hash);
+        Ok(())
+    }
+
+    /// Deploys the escrow for an order at `escrow_address(order_id, owner, escrow_wasm_hash)`
+    /// with the current escrow Wasm, initializes it and funds it with `amount` from the owner.
+    /// Returns the escrow's address.
+    pub fn create_escrow(env: Env, params: EscrowParams) -> Result<Address, Error> {
+        params.owner.require_auth();
+        Self::extend_instance_ttl(&env);
+
+        let escrow_wasm_hash: BytesN<32> = env.storage().instance()
+            .get(&DataKey::EscrowWasm)
+            .ok_or(Error::NotInitialized)?;
+
+        // Deploying the same order twice fails, as the address is already taken
+        let salt = Self::escrow_salt(&env, params.order_id.clone(), params.owner.clone(), escrow_wasm_hash.clone());
+        let escrow = env.deployer()
+            .with_current_contract(salt)
+            .deploy(escrow_wasm_hash);
+        OrderEscrowClient::new(&env, &escrow).init(&params);
+
+        token::Client::new(&env, &params.token_contract).transfer(
+            &params.owner,
+            &escrow,
+            &params.amount,
+        );
+
+        let event = EscrowDeployedEvent {
+            order_id: params.order_id.clone(),
+            hash: params.hash.clone(),
+            owner: params.owner.clone(),
+            taker: params.taker.clone(),
+            escrow: escrow.clone(),
+            amount: params.amount,
+        };
+        env.events().publish(
+            (symbol_short!("escrow"), symbol_short!("deployed"), params.hash, params.owner),
+            event,
+        );
+
+        log!(&env, "Escrow deployed: order_id={}, escrow={}", params.order_id, escrow);
+        Ok(escrow)
+    }
+
+    /// Address the escrow for an order is (or will be) deployed at when running
+    /// `escrow_wasm_hash`. Off-chain, it is the contract id derived from this factory's
+    /// address and the salt sha256(xdr((order_id, owner, escrow_wasm_hash))), so funds sent
+    /// there can only be held by that code.
+    pub fn escrow_address(env: Env, order_id: String, owner: Address, escrow_wasm_hash: BytesN<32>) -> Address {
+        let salt = Self::escrow_salt(&env, order_id, owner, escrow_wasm_hash);
+        env.deployer().with_current_contract(salt).deployed_address()
+    }
+
+    /// Gets the current admin.
+    pub fn get_admin(env: Env) -> Result<Address, Error> {
+        env.storage().instance()
+            .get(&DataKey::Admin)
+            .ok_or(Error::NotInitialized)
+    }
+
+    /// Gets the hash of the Wasm deployed for new orders.
+    pub fn get_escrow_wasm(env: Env) -> Result<BytesN<32>, Error> {
+        env.storage().instance()
+            .get(&DataKey::EscrowWasm)
+            .ok_or(Error::NotInitialized)
+    }
+
+    fn extend_instance_ttl(env: &Env) {
+        env.storage()
+            .instance()
+            .extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+    }
+
+    fn escrow_salt(env: &Env, order_id: String, owner: Address, escrow_wasm_hash: BytesN<32>) -> BytesN<32> {
+        env.crypto().sha256(&(order_id, owner, escrow_wasm_hash).to_xdr(env)).into()
+    }
+}
+
+#[cfg(test)]
+mod test;
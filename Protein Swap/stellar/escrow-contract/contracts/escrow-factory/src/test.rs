@@ -0,0 +1,253 @@
+#![cfg(test)]
+extern crate std;
+
+use super::*;
+use order_escrow::EscrowStatus;
+use soroban_sdk::testutils::{Address as _, Events, Ledger};
+use soroban_sdk::token::{StellarAssetClient, TokenClient};
+use soroban_sdk::xdr::{
+    ContractIdPreimage, ContractIdPreimageFromAddress, Hash, HashIdPreimage, HashIdPreimageContractId, Limits,
+    ScAddress, Uint256, WriteXdr,
+};
+use soroban_sdk::{Bytes, Env, IntoVal, TryFromVal};
+
+// The order escrow's built Wasm and client. Deploying needs a real Wasm hash, so build the
+// order escrow first (`make test` does).
+mod order_escrow {
+    soroban_sdk::contractimport!(file = "../../target/wasm32v1-none/release/order_escrow.wasm");
+}
+
+const AMOUNT: i128 = 1000;
+const TIMELOCK: u64 = 3600;
+
+struct Setup<'a> {
+    env: Env,
+    factory: EscrowFactoryContractClient<'a>,
+    token: TokenClient<'a>,
+    admin: Address,
+    owner: Address,
+    taker: Address,
+}
+
+fn setup<'a>() -> Setup<'a> {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let factory_id = env.register_contract(None, EscrowFactoryContract);
+    let factory = EscrowFactoryContractClient::new(&env, &factory_id);
+    let token_id = env.register_stellar_asset_contract_v2(Address::generate(&env)).address();
+    let token = TokenClient::new(&env, &token_id);
+
+    let admin = Address::generate(&env);
+    let owner = Address::generate(&env);
+    let taker = Address::generate(&env);
+    StellarAssetClient::new(&env, &token_id).mint(&owner, &(AMOUNT * 10));
+
+    factory.initialize(&admin, &test_escrow_wasm(&env));
+
+    Setup {
+        env,
+        factory,
+        token,
+        admin,
+        owner,
+        taker,
+    }
+}
+
+// Escrows are deployed from the order escrow's built Wasm, as on a network
+fn test_escrow_wasm(env: &Env) -> BytesN<32> {
+    env.deployer().upload_contract_wasm(order_escrow::WASM)
+}
+
+fn secret(env: &Env) -> Bytes {
+    Bytes::from_slice(env, &[1, 2, 3, 4, 5])
+}
+
+fn params(s: &Setup, order_id: &String) -> EscrowParams {
+    EscrowParams {
+        order_id: order_id.clone(),
+        hash: s.env.crypto().sha256(&secret(&s.env)).into(),
+        hash_algo: HashAlgo::Sha256,
+        owner: s.owner.clone(),
+        taker: s.taker.clone(),
+        token_contract: s.token.address.clone(),
+        amount: AMOUNT,
+        timelocks: Timelocks {
+            withdrawal: 0,
+            public_withdrawal: TIMELOCK / 2,
+            cancellation: TIMELOCK,
+            public_cancellation: TIMELOCK + 600,
+        },
+    }
+}
+
+#[test]
+fn test_initialize_twice() {
+    let s = setup();
+
+    assert_eq!(s.factory.get_admin(), s.admin);
+    assert_eq!(s.factory.get_escrow_wasm(), test_escrow_wasm(&s.env));
+    assert_eq!(
+        s.factory.try_initialize(&s.owner, &test_escrow_wasm(&s.env)),
+        Err(Ok(Error::AlreadyInitialized))
+    );
+}
+
+#[test]
+fn test_create_escrow_not_initialized() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let factory = EscrowFactoryContractClient::new(&env, &env.register_contract(None, EscrowFactoryContract));
+    let s = Setup {
+        token: TokenClient::new(&env, &Address::generate(&env)),
+        admin: Address::generate(&env),
+        owner: Address::generate(&env),
+        taker: Address::generate(&env),
+        factory,
+        env,
+    };
+    let order_id = String::from_str(&s.env, "test_order_001");
+
+    assert_eq!(
+        s.factory.try_create_escrow(&params(&s, &order_id)),
+        Err(Ok(Error::NotInitialized))
+    );
+}
+
+#[test]
+fn test_escrow_address_matches_off_chain_derivation() {
+    let s = setup();
+    let order_id = String::from_str(&s.env, "test_order_001");
+
+    // The same derivation a client runs before funding: salt, then the contract id preimage
+    let wasm_hash = test_escrow_wasm(&s.env);
+    let salt: BytesN<32> = s.env.crypto()
+        .sha256(&(order_id.clone(), s.owner.clone(), wasm_hash.clone()).to_xdr(&s.env))
+        .into();
+    let preimage = HashIdPreimage::ContractId(HashIdPreimageContractId {
+        network_id: Hash(s.env.ledger().network_id().to_array()),
+        contract_id_preimage: ContractIdPreimage::Address(ContractIdPreimageFromAddress {
+            address: ScAddress::from(&s.factory.address),
+            salt: Uint256(salt.to_array()),
+        }),
+    });
+    let preimage = preimage.to_xdr(Limits::none()).unwrap();
+    let contract_id = s.env.crypto().sha256(&Bytes::from_slice(&s.env, &preimage));
+
+    assert_eq!(
+        ScAddress::from(&s.factory.escrow_address(&order_id, &s.owner, &wasm_hash)),
+        ScAddress::Contract(Hash(contract_id.to_array()))
+    );
+}
+
+#[test]
+fn test_escrow_address_per_order_and_owner() {
+    let s = setup();
+    let first = String::from_str(&s.env, "test_order_001");
+    let second = String::from_str(&s.env, "test_order_002");
+    let other_owner = Address::generate(&s.env);
+    let wasm_hash = test_escrow_wasm(&s.env);
+
+    let address = s.factory.escrow_address(&first, &s.owner, &wasm_hash);
+    assert_eq!(s.factory.escrow_address(&first, &s.owner, &wasm_hash), address);
+    assert_ne!(s.factory.escrow_address(&second, &s.owner, &wasm_hash), address);
+    assert_ne!(s.factory.escrow_address(&first, &other_owner, &wasm_hash), address);
+}
+
+// An address commits to the code deployed there, so switching the escrow Wasm can't put
+// different code at an address a taker already checked
+#[test]
+fn test_escrow_address_per_wasm() {
+    let s = setup();
+    let order_id = String::from_str(&s.env, "test_order_001");
+    let old_hash = test_escrow_wasm(&s.env);
+    let new_hash = BytesN::from_array(&s.env, &[7; 32]);
+
+    let old_address = s.factory.escrow_address(&order_id, &s.owner, &old_hash);
+    let new_address = s.factory.escrow_address(&order_id, &s.owner, &new_hash);
+    assert_ne!(new_address, old_address);
+
+    // Escrows are deployed at the address for the Wasm they run
+    assert_eq!(s.factory.create_escrow(&params(&s, &order_id)), old_address);
+}
+
+#[test]
+fn test_create_escrow_deploys_funded_escrow() {
+    let s = setup();
+    let order_id = String::from_str(&s.env, "test_order_001");
+    let predicted = s.factory.escrow_address(&order_id, &s.owner, &s.factory.get_escrow_wasm());
+
+    let escrow = s.factory.create_escrow(&params(&s, &order_id));
+
+    assert_eq!(escrow, predicted);
+    assert_eq!(s.token.balance(&escrow), AMOUNT);
+    assert_eq!(s.token.balance(&s.owner), AMOUNT * 9);
+
+    let client = order_escrow::Client::new(&s.env, &escrow);
+    let stored = client.get_escrow();
+    assert_eq!(stored.order_id, order_id);
+    assert_eq!(stored.owner, s.owner);
+    assert_eq!(stored.taker, s.taker);
+    assert_eq!(stored.timelock, TIMELOCK);
+
+    let (contract, topics, data) = s.env.events().all().last().unwrap();
+    assert_eq!(contract, s.factory.address);
+    assert_eq!(
+        topics,
+        (symbol_short!("escrow"), symbol_short!("deployed"), stored.hash.clone(), s.owner.clone()).into_val(&s.env)
+    );
+    assert_eq!(EscrowDeployedEvent::try_from_val(&s.env, &data).unwrap().escrow, escrow);
+
+    // The deployed escrow settles on its own
+    client.withdraw(&s.taker, &secret(&s.env));
+    assert_eq!(s.token.balance(&s.taker), AMOUNT);
+    assert_eq!(client.get_escrow().status, EscrowStatus::Completed);
+}
+
+#[test]
+fn test_deployed_escrow_cancel() {
+    let s = setup();
+    let order_id = String::from_str(&s.env, "test_order_001");
+    let escrow = s.factory.create_escrow(&params(&s, &order_id));
+
+    s.env.ledger().set_timestamp(TIMELOCK);
+    order_escrow::Client::new(&s.env, &escrow).cancel(&s.owner);
+
+    assert_eq!(s.token.balance(&s.owner), AMOUNT * 10);
+    assert_eq!(s.token.balance(&escrow), 0);
+}
+
+#[test]
+fn test_create_escrow_twice() {
+    let s = setup();
+    let order_id = String::from_str(&s.env, "test_order_001");
+    s.factory.create_escrow(&params(&s, &order_id));
+
+    assert!(s.factory.try_create_escrow(&params(&s, &order_id)).is_err());
+    assert_eq!(s.token.balance(&s.owner), AMOUNT * 9);
+}
+
+#[test]
+fn test_create_escrow_invalid_params() {
+    let s = setup();
+    let order_id = String::from_str(&s.env, "test_order_001");
+    let mut invalid = params(&s, &order_id);
+    invalid.amount = 0;
+
+    // The escrow's own validation rejects it, reverting the deploy
+    assert!(s.factory.try_create_escrow(&invalid).is_err());
+    assert_eq!(s.token.balance(&s.owner), AMOUNT * 10);
+}
+
+#[test]
+fn test_set_escrow_wasm() {
+    let s = setup();
+    let new_hash = BytesN::from_array(&s.env, &[7; 32]);
+
+    s.factory.set_escrow_wasm(&new_hash);
+
+    let (address, _) = s.env.auths().pop().unwrap();
+    assert_eq!(address, s.admin);
+    assert_eq!(s.factory.get_escrow_wasm(), new_hash);
+}
@@ -1,7 +1,46 @@
 #![no_std]
+// create_escrow takes the full order description as arguments
+#![allow(clippy::too_many_arguments)]
 use soroban_sdk::{
-    contract, contractimpl, contracttype, contracterror, log, symbol_short, Address, Bytes, Env, IntoVal, String, Symbol
+    contract, contractimpl, contracttype, contracterror, log, symbol_short, token, Address, Bytes, BytesN, Env, IntoVal, Map, String, Symbol, TryFromVal, Val, Vec
 };
+use soroban_sdk::xdr::ToXdr;
+
+// Version of the stored data layout; bump it and extend `migrate` when `Escrow` changes
+const CONTRACT_VERSION: u32 = 3;
+
+// Ledgers close roughly every 5 seconds
+const LEDGER_SECONDS: u64 = 5;
+const DAY_IN_LEDGERS: u32 = 17_280;
+
+// Instance storage (admin) is bumped back to 30 days once it drops below 29
+const INSTANCE_BUMP_AMOUNT: u32 = 30 * DAY_IN_LEDGERS;
+const INSTANCE_LIFETIME_THRESHOLD: u32 = INSTANCE_BUMP_AMOUNT - DAY_IN_LEDGERS;
+
+// Escrows stay live this long past their last window so they can still be finalized
+const ESCROW_TTL_MARGIN: u32 = 7 * DAY_IN_LEDGERS;
+
+// Per-user escrow indices are stored in chunks to stay within ledger entry size limits
+const INDEX_CHUNK_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 50;
+
+// Index entries are bumped back to 30 days whenever they are written or read, so an older
+// chunk doesn't expire while newer ones are still in use
+const INDEX_BUMP_AMOUNT: u32 = 30 * DAY_IN_LEDGERS;
+const INDEX_LIFETIME_THRESHOLD: u32 = INDEX_BUMP_AMOUNT - DAY_IN_LEDGERS;
+
+// In the worst case, every order of a batch of reveals uses its own token and taker, so it
+// writes its escrow and secret entries and the contract's and taker's balances of its token,
+// plus three shared entries (the contract's and caller's native balances, the caller's nonce).
+// 5 orders take 23 of the 25 ledger entry writes a transaction is allowed; cancels write less.
+const MAX_BATCH_SIZE: u32 = 5;
+
+// Partially fillable escrows track filled parts in a 64-bit mask
+const MAX_PARTS: u32 = 64;
+
+// Bounds on the cancellation and public cancellation offsets, in seconds
+const MIN_TIMELOCK_DURATION: u64 = 5 * 60;
+const MAX_TIMELOCK_DURATION: u64 = 30 * 24 * 60 * 60;
 
 // Status enum for escrow lifecycle
 #[contracttype]
@@ -12,25 +51,132 @@ pub enum EscrowStatus {
     Cancelled,
 }
 
+// Hash function the hashlock was built with, so one secret can lock legs on chains that
+// use different hashes (e.g. keccak256 on Sui)
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HashAlgo {
+    Sha256,
+    Keccak256,
+}
+
+// Offsets from creation, in seconds, at which each finalization window opens
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Timelocks {
+    pub withdrawal: u64,          // taker can reveal
+    pub public_withdrawal: u64,   // anyone can reveal on the taker's behalf
+    pub cancellation: u64,        // reveals close, owner can cancel
+    pub public_cancellation: u64, // anyone can cancel on the owner's behalf
+}
+
 // Main escrow data structure
 #[contracttype]
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Escrow {
     pub order_id: String,
-    pub hash: Bytes,
+    pub hash: BytesN<32>,
+    pub hash_algo: HashAlgo,
     pub owner: Address,
     pub taker: Address,
     pub token_contract: Address,
     pub amount: i128,
-    pub timelock: u64,
+    pub safety_deposit: i128,        // native token, paid to whoever finalizes
+    pub withdrawal_at: u64,
+    pub public_withdrawal_at: u64,
+    pub timelock: u64,               // cancellation window start
+    pub public_cancellation_at: u64,
     pub status: EscrowStatus,
     pub created_at: u64,
+    pub secret: Option<Bytes>, // set once the taker reveals it
+    pub parts: u32,            // 0 for a single secret, else the Merkle tree's leaf count
+    pub filled: i128,          // amount already released to the taker
+    pub filled_parts: u64,     // bit i set once part i is revealed
+}
+
+// Escrow layout before version 3, which had no partial fills. Only read by `migrate`.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct EscrowV2 {
+    order_id: String,
+    hash: BytesN<32>,
+    hash_algo: HashAlgo,
+    owner: Address,
+    taker: Address,
+    token_contract: Address,
+    amount: i128,
+    safety_deposit: i128,
+    withdrawal_at: u64,
+    public_withdrawal_at: u64,
+    timelock: u64,
+    public_cancellation_at: u64,
+    status: EscrowStatus,
+    created_at: u64,
+    secret: Option<Bytes>,
+}
+
+// Escrow layout before version 2, which had no hash_algo and always used SHA-256.
+// Only read by `migrate`.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct EscrowV1 {
+    order_id: String,
+    hash: BytesN<32>,
+    owner: Address,
+    taker: Address,
+    token_contract: Address,
+    amount: i128,
+    safety_deposit: i128,
+    withdrawal_at: u64,
+    public_withdrawal_at: u64,
+    timelock: u64,
+    public_cancellation_at: u64,
+    status: EscrowStatus,
+    created_at: u64,
+    secret: Option<Bytes>,
+}
+
+// One order of a `reveal_many` batch
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RevealArgs {
+    pub order_id: String,
+    pub owner: Address,
+    pub secret: Bytes,
+}
+
+// Outcome of one order of a batch call
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BatchResult {
+    Done,
+    Failed(u32), // the `Error` code the order failed with
+}
+
+impl From<Result<(), Error>> for BatchResult {
+    fn from(result: Result<(), Error>) -> Self {
+        match result {
+            Ok(()) => BatchResult::Done,
+            Err(err) => BatchResult::Failed(err as u32),
+        }
+    }
 }
 
 // Storage keys for persistent storage
 #[contracttype]
 pub enum DataKey {
     Escrow(String, Address), // {order_id}:{owner}
+    Admin,                   // instance storage
+    PendingAdmin,            // instance storage, set by set_admin until accepted
+    NativeToken,             // instance storage, token safety deposits are paid in
+    Version,                 // instance storage, layout version of stored data
+    Secret(BytesN<32>),      // revealed secret, indexed by its hash
+    OwnerIndex(Address, u32), // chunk of order ids created by an owner
+    OwnerCount(Address),     // number of escrows created by an owner
+    TakerIndex(Address, u32), // chunk of (order_id, owner) pairs a taker can claim
+    TakerCount(Address),     // number of escrows naming a taker
+    RevealKey(Address),      // ed25519 key a taker signs relayed reveals with
+    RevealNonce(Address),    // next nonce for a taker's signed reveal
 }
 
 // Custom error types
@@ -46,16 +192,37 @@ pub enum Error {
     InvalidAmount = 6,
     EscrowAlreadyExists = 7,
     EscrowNotActive = 8,
+    AlreadyInitialized = 9,
+    NotInitialized = 10,
+    NoPendingAdmin = 11,
+    SecretNotRevealed = 12,
+    InvalidHash = 13,
+    InvalidTimelock = 14,
+    NoRevealKey = 15,
+    InvalidNonce = 16,
+    WithdrawalNotStarted = 17,
+    PartialFillsDisabled = 18,
+    InvalidPartIndex = 19,
+    PartAlreadyFilled = 20,
+    InvalidProof = 21,
+    BatchTooLarge = 22,
+    PartialFillRequired = 23,
+    TooManyParts = 24,
 }
 
-// Event types for logging
+// Event types for logging. Escrow events are published under ("escrow", <action>, hash, party)
+// topics, the party being the owner on creation and otherwise the one paid, so indexers can
+// filter by party and watchers by hash.
 #[contracttype]
 pub struct EscrowCreatedEvent {
     pub order_id: String,
+    pub hash: BytesN<32>,
+    pub hash_algo: HashAlgo,
     pub owner: Address,
     pub taker: Address,
     pub token_contract: Address,
     pub amount: i128,
+    pub safety_deposit: i128,
     pub timelock: u64,
 }
 
@@ -65,6 +232,17 @@ pub struct EscrowCompletedEvent {
     pub owner: Address,
     pub taker: Address,
     pub amount: i128,
+    pub secret: Bytes,
+}
+
+#[contracttype]
+pub struct EscrowPartFilledEvent {
+    pub order_id: String,
+    pub owner: Address,
+    pub taker: Address,
+    pub index: u32,
+    pub amount: i128,
+    pub secret: Bytes,
 }
 
 #[contracttype]
@@ -79,24 +257,166 @@ pub struct AtomicSwapEscrowContract;
 
 #[contractimpl]
 impl AtomicSwapEscrowContract {
-    /// Creates a new escrow with hashlock and timelock mechanisms
+    /// Sets the admin, who may reveal and cancel any escrow, and the native token contract
+    /// safety deposits are paid in. Can only be called once.
+    pub fn initialize(env: Env, admin: Address, native_token: Address) -> Result<(), Error> {
+        if env.storage().instance().has(&DataKey::Admin) {
+            return Err(Error::AlreadyInitialized);
+        }
+        admin.require_auth();
+
+        env.storage().instance().set(&DataKey::Admin, &admin);
+        env.storage().instance().set(&DataKey::NativeToken, &native_token);
+        env.storage().instance().set(&DataKey::Version, &CONTRACT_VERSION);
+        Self::extend_instance_ttl(&env);
+        env.events().publish((Symbol::new(&env, "admin_changed"),), admin);
+        Ok(())
+    }
+
+    /// Proposes a new admin; it takes over once it calls `accept_admin`
+    pub fn set_admin(env: Env, new_admin: Address) -> Result<(), Error> {
+        let admin = Self::get_admin(env.clone())?;
+        admin.require_auth();
+        Self::extend_instance_ttl(&env);
+
+        env.storage().instance().set(&DataKey::PendingAdmin, &new_admin);
+        env.events().publish((Symbol::new(&env, "admin_proposed"),), new_admin);
+        Ok(())
+    }
+
+    /// Completes an admin rotation started by `set_admin`
+    pub fn accept_admin(env: Env) -> Result<(), Error> {
+        let new_admin: Address = env.storage().instance()
+            .get(&DataKey::PendingAdmin)
+            .ok_or(Error::NoPendingAdmin)?;
+        new_admin.require_auth();
+        Self::extend_instance_ttl(&env);
+
+        env.storage().instance().set(&DataKey::Admin, &new_admin);
+        env.storage().instance().remove(&DataKey::PendingAdmin);
+        env.events().publish((Symbol::new(&env, "admin_changed"),), new_admin);
+        Ok(())
+    }
+
+    /// Returns the current admin
+    pub fn get_admin(env: Env) -> Result<Address, Error> {
+        Self::extend_instance_ttl(&env);
+        env.storage().instance()
+            .get(&DataKey::Admin)
+            .ok_or(Error::NotInitialized)
+    }
+
+    /// Returns the token contract safety deposits are paid in
+    pub fn get_native_token(env: Env) -> Result<Address, Error> {
+        Self::extend_instance_ttl(&env);
+        env.storage().instance()
+            .get(&DataKey::NativeToken)
+            .ok_or(Error::NotInitialized)
+    }
+
+    /// Replaces the contract code with an uploaded WASM, keeping its storage and address.
+    /// Call `migrate` and `finish_migration` afterwards if the new code changes the stored layout.
+    pub fn upgrade(env: Env, new_wasm_hash: BytesN<32>) -> Result<(), Error> {
+        let admin = Self::get_admin(env.clone())?;
+        admin.require_auth();
+
+        env.deployer().update_current_contract_wasm(new_wasm_hash.clone());
+        env.events().publish((symbol_short!("upgraded"),), new_wasm_hash);
+        Ok(())
+    }
+
+    /// Rewrites the given escrows, identified by (order_id, owner), in the current layout.
+    /// Can be called in batches; returns the escrows that couldn't be rewritten because they
+    /// don't exist or aren't in a known layout. Call `finish_migration` once every stored
+    /// escrow has been rewritten.
+    pub fn migrate(env: Env, escrows: Vec<(String, Address)>) -> Result<Vec<(String, Address)>, Error> {
+        let admin = Self::get_admin(env.clone())?;
+        admin.require_auth();
+        Self::extend_instance_ttl(&env);
+
+        let mut failed = Vec::new(&env);
+        for (order_id, owner) in escrows.iter() {
+            let key = DataKey::Escrow(order_id.clone(), owner.clone());
+            let stored: Option<Val> = env.storage().persistent().get(&key);
+            match stored.and_then(|raw| Self::migrate_escrow(&env, raw)) {
+                Some(escrow) => {
+                    env.storage().persistent().set(&key, &escrow);
+                    Self::extend_escrow_entry_ttl(&env, &key, escrow.public_cancellation_at);
+                }
+                None => failed.push_back((order_id, owner)),
+            }
+        }
+        Ok(failed)
+    }
+
+    /// Records the current layout as the version of the stored data. The contract can't
+    /// enumerate its escrows, so this is the admin's declaration that `migrate` has rewritten
+    /// all of them.
+    pub fn finish_migration(env: Env) -> Result<(), Error> {
+        let admin = Self::get_admin(env.clone())?;
+        admin.require_auth();
+
+        let previous = Self::version(env.clone());
+        env.storage().instance().set(&DataKey::Version, &CONTRACT_VERSION);
+        env.events().publish((symbol_short!("migrated"), previous), CONTRACT_VERSION);
+        Ok(())
+    }
+
+    /// Returns the layout version the admin last declared all stored escrows to be in, with
+    /// `finish_migration`. Escrows not yet passed to `migrate` may still be in an older one.
+    /// Deployments from before versioning never recorded one, so they report 1.
+    pub fn version(env: Env) -> u32 {
+        Self::extend_instance_ttl(&env);
+        env.storage().instance().get(&DataKey::Version).unwrap_or(1)
+    }
+
+    /// Returns the admin proposed by `set_admin`, if any
+    pub fn get_pending_admin(env: Env) -> Option<Address> {
+        Self::extend_instance_ttl(&env);
+        env.storage().instance().get(&DataKey::PendingAdmin)
+    }
+
+    /// Creates a new escrow with hashlock and timelock mechanisms. `hash` is the `hash_algo`
+    /// digest of the secret or, when `parts` is non-zero, the root of a Merkle tree of `parts`
+    /// secrets that each release a proportional tranche via `reveal_partial`. The owner also locks
+    /// `safety_deposit` of the native token, paid to whoever finalizes the escrow.
     pub fn create_escrow(
         env: Env,
         owner: Address,
         order_id: String,
-        hash: Bytes,
+        hash: BytesN<32>,
+        hash_algo: HashAlgo,
         taker: Address,
         token_contract: Address,
         amount: i128,
-        timelock_duration: u64,
+        timelocks: Timelocks,
+        safety_deposit: i128,
+        parts: u32,
     ) -> Result<(), Error> {
         // Require authorization from the owner
         owner.require_auth();
+        Self::extend_instance_ttl(&env);
 
-        // Validate amount
-        if amount == 0 {
+        // Validate amounts, hashlock and timelocks
+        if amount <= 0 || safety_deposit < 0 || amount < parts as i128 {
             return Err(Error::InvalidAmount);
         }
+        if parts > MAX_PARTS {
+            return Err(Error::TooManyParts);
+        }
+        if hash == BytesN::from_array(&env, &[0; 32]) {
+            return Err(Error::InvalidHash);
+        }
+        let windows_in_order = timelocks.withdrawal <= timelocks.public_withdrawal
+            && timelocks.withdrawal < timelocks.cancellation
+            && timelocks.public_withdrawal <= timelocks.cancellation
+            && timelocks.cancellation <= timelocks.public_cancellation;
+        if !windows_in_order
+            || timelocks.cancellation < MIN_TIMELOCK_DURATION
+            || timelocks.public_cancellation > MAX_TIMELOCK_DURATION
+        {
+            return Err(Error::InvalidTimelock);
+        }
 
         // Check if escrow already exists
         let key = DataKey::Escrow(order_id.clone(), owner.clone());
@@ -104,59 +424,95 @@ impl AtomicSwapEscrowContract {
             return Err(Error::EscrowAlreadyExists);
         }
 
-        // Get current ledger timestamp
+        // Get current ledger timestamp; the last window bounds the others
         let current_time = env.ledger().timestamp();
-        let timelock = current_time + timelock_duration;
+        let public_cancellation_at = current_time
+            .checked_add(timelocks.public_cancellation)
+            .ok_or(Error::InvalidTimelock)?;
+        let timelock = current_time + timelocks.cancellation;
 
         // Create escrow struct
         let escrow = Escrow {
             order_id: order_id.clone(),
             hash: hash.clone(),
+            hash_algo,
             owner: owner.clone(),
             taker: taker.clone(),
             token_contract: token_contract.clone(),
             amount,
+            safety_deposit,
+            withdrawal_at: current_time + timelocks.withdrawal,
+            public_withdrawal_at: current_time + timelocks.public_withdrawal,
             timelock,
+            public_cancellation_at,
             status: EscrowStatus::Active,
             created_at: current_time,
+            secret: None,
+            parts,
+            filled: 0,
+            filled_parts: 0,
         };
 
-        // Transfer tokens from owner to contract
-        let contract_address = env.current_contract_address();
-        let transfer_args = soroban_sdk::vec![
-            &env, 
-            owner.into_val(&env), 
-            contract_address.into_val(&env), 
-            amount.into_val(&env)
-        ];
-        
-        // Fix: Add explicit type annotation for invoke_contract
-        let _result: () = env.invoke_contract(
-            &token_contract,
-            &Symbol::new(&env, "transfer"),
-            transfer_args,
+        // Transfer tokens and the safety deposit from owner to contract
+        token::Client::new(&env, &token_contract).transfer(
+            &owner,
+            &env.current_contract_address(),
+            &amount,
         );
+        if safety_deposit > 0 {
+            let native_token = Self::get_native_token(env.clone())?;
+            token::Client::new(&env, &native_token).transfer(
+                &owner,
+                &env.current_contract_address(),
+                &safety_deposit,
+            );
+        }
 
-        // Store escrow in persistent storage
+        // Store escrow in persistent storage, live until past its last window
         env.storage().persistent().set(&key, &escrow);
+        Self::extend_escrow_entry_ttl(&env, &key, public_cancellation_at);
+
+        // Index it for both parties
+        Self::push_index(
+            &env,
+            DataKey::OwnerCount(owner.clone()),
+            |chunk| DataKey::OwnerIndex(owner.clone(), chunk),
+            order_id.clone(),
+            public_cancellation_at,
+        );
+        Self::push_index(
+            &env,
+            DataKey::TakerCount(taker.clone()),
+            |chunk| DataKey::TakerIndex(taker.clone(), chunk),
+            (order_id.clone(), owner.clone()),
+            public_cancellation_at,
+        );
 
         // Emit EscrowCreated event
         let event = EscrowCreatedEvent {
             order_id: order_id.clone(),
+            hash: hash.clone(),
+            hash_algo,
             owner: owner.clone(),
             taker: taker.clone(),
             token_contract: token_contract.clone(),
             amount,
+            safety_deposit,
             timelock,
         };
 
-        env.events().publish((symbol_short!("created"),), event);
+        env.events().publish(
+            (symbol_short!("escrow"), symbol_short!("created"), hash, owner),
+            event,
+        );
 
         log!(&env, "Escrow created: order_id={}, amount={}", order_id, amount);
         Ok(())
     }
 
-    /// Reveals the secret to complete the escrow swap
+    /// Reveals the secret to complete the escrow swap. The taker or admin can reveal once the
+    /// withdrawal window opens, anyone once the public withdrawal window opens; the caller
+    /// receives the safety deposit.
     pub fn reveal_secret(
         env: Env,
         caller: Address,
@@ -166,83 +522,147 @@ impl AtomicSwapEscrowContract {
     ) -> Result<(), Error> {
         // Require authorization from the caller
         caller.require_auth();
-        
-        // Get escrow
+        Self::extend_instance_ttl(&env);
+
+        Self::reveal(&env, &caller, order_id, owner, secret)
+    }
+
+    /// Reveals one secret of a partially fillable escrow, with its Merkle proof, releasing
+    /// that part's tranche of the amount to the taker and of the safety deposit to the caller.
+    /// The escrow completes once every part is filled.
+    pub fn reveal_partial(
+        env: Env,
+        caller: Address,
+        order_id: String,
+        owner: Address,
+        secret: Bytes,
+        index: u32,
+        proof: Vec<BytesN<32>>,
+    ) -> Result<(), Error> {
+        caller.require_auth();
+        Self::extend_instance_ttl(&env);
+
         let key = DataKey::Escrow(order_id.clone(), owner.clone());
         let mut escrow: Escrow = env.storage().persistent()
             .get(&key)
             .ok_or(Error::EscrowNotFound)?;
-
-        // Check if escrow is active
         if escrow.status != EscrowStatus::Active {
             return Err(Error::EscrowNotActive);
         }
-
-        // Only taker or contract deployer can reveal the secret
-        let contract_deployer = Address::from_string(&String::from_str(&env, "GDGFQGWD6DE6ZZO6F5SWBDB7N7RCYCW4B36IMNNLJKQHOYIKRSSVU6E2"));
-        if caller != escrow.taker && caller != contract_deployer {
-            return Err(Error::NotAuthorized);
+        if escrow.parts == 0 {
+            return Err(Error::PartialFillsDisabled);
         }
-
-        // Check if timelock has not expired
-        let current_time = env.ledger().timestamp();
-        if current_time >= escrow.timelock {
+        if index >= escrow.parts {
+            return Err(Error::InvalidPartIndex);
+        }
+        if escrow.filled_parts & (1 << index) != 0 {
+            return Err(Error::PartAlreadyFilled);
+        }
+        Self::check_reveal_window(&env, &escrow, &caller)?;
+        if env.ledger().timestamp() >= escrow.timelock {
             return Err(Error::TimelockExpired);
         }
 
-        // Verify hash matches secret
-        let computed_hash: Bytes = env.crypto().sha256(&secret).into();
-        if computed_hash != escrow.hash {
-            return Err(Error::HashMismatch);
+        // Leaves are H(index || H(secret)); pairs are hashed in sorted order
+        let secret_hash = Self::hash_secret(&env, &secret, escrow.hash_algo);
+        let mut leaf = Bytes::from_array(&env, &index.to_be_bytes());
+        leaf.append(&secret_hash.clone().into());
+        let mut node = Self::hash_secret(&env, &leaf, escrow.hash_algo);
+        for sibling in proof.iter() {
+            let (left, right) = if node < sibling { (node, sibling) } else { (sibling, node) };
+            let mut pair: Bytes = left.into();
+            pair.append(&right.into());
+            node = Self::hash_secret(&env, &pair, escrow.hash_algo);
+        }
+        if node != escrow.hash {
+            return Err(Error::InvalidProof);
         }
 
-        // Transfer tokens to taker
-        let contract_address = env.current_contract_address();
-        let transfer_args = soroban_sdk::vec![
-            &env, 
-            contract_address.into_val(&env), 
-            escrow.taker.into_val(&env), 
-            escrow.amount.into_val(&env)
-        ];
-        
-        // Fix: Add explicit type annotation for invoke_contract
-        let _result: () = env.invoke_contract(
-            &escrow.token_contract,
-            &Symbol::new(&env, "transfer"),
-            transfer_args,
+        // Release this part's tranche of the amount and the safety deposit
+        let amount = Self::tranche(escrow.amount, index, escrow.parts);
+        token::Client::new(&env, &escrow.token_contract).transfer(
+            &env.current_contract_address(),
+            &escrow.taker,
+            &amount,
         );
+        let deposit = Self::tranche(escrow.safety_deposit, index, escrow.parts);
+        Self::pay_safety_deposit(&env, deposit, &caller);
 
-        // Update escrow status
-        escrow.status = EscrowStatus::Completed;
+        escrow.filled += amount;
+        escrow.filled_parts |= 1 << index;
+        escrow.secret = Some(secret.clone());
+        if escrow.filled_parts.count_ones() == escrow.parts {
+            escrow.status = EscrowStatus::Completed;
+        }
         env.storage().persistent().set(&key, &escrow);
 
-        // Emit EscrowCompleted event
-        let event = EscrowCompletedEvent {
+        let secret_key = DataKey::Secret(secret_hash);
+        env.storage().persistent().set(&secret_key, &secret);
+        env.storage().persistent().extend_ttl(&secret_key, ESCROW_TTL_MARGIN, ESCROW_TTL_MARGIN);
+
+        let event = EscrowPartFilledEvent {
             order_id: order_id.clone(),
-            owner: owner.clone(),
+            owner,
             taker: escrow.taker.clone(),
-            amount: escrow.amount,
+            index,
+            amount,
+            secret,
         };
+        env.events().publish(
+            (symbol_short!("escrow"), symbol_short!("filled"), escrow.hash, escrow.taker),
+            event,
+        );
 
-        env.events().publish((symbol_short!("completed"),), event);
-
-        log!(&env, "Escrow completed: order_id={}, amount={}", order_id, escrow.amount);
+        log!(&env, "Escrow part filled: order_id={}, index={}, amount={}", order_id, index, amount);
         Ok(())
     }
 
-    /// Cancels an escrow after timelock expiry, returning tokens to owner
-    pub fn cancel_escrow(
+    /// Registers the ed25519 public key whose signatures `reveal_secret_signed` accepts
+    /// on behalf of `taker`
+    pub fn set_reveal_key(env: Env, taker: Address, public_key: BytesN<32>) {
+        taker.require_auth();
+        Self::extend_instance_ttl(&env);
+
+        let key = DataKey::RevealKey(taker);
+        env.storage().persistent().set(&key, &public_key);
+        env.storage().persistent().extend_ttl(&key, INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+    }
+
+    /// Returns the reveal key registered by `taker`, if any
+    pub fn get_reveal_key(env: Env, taker: Address) -> Option<BytesN<32>> {
+        Self::extend_instance_ttl(&env);
+        env.storage().persistent().get(&DataKey::RevealKey(taker))
+    }
+
+    /// Returns the nonce the next signed reveal by `taker` must use
+    pub fn get_reveal_nonce(env: Env, taker: Address) -> u64 {
+        Self::extend_instance_ttl(&env);
+        env.storage().persistent().get(&DataKey::RevealNonce(taker)).unwrap_or(0)
+    }
+
+    /// Returns the payload the taker signs for `reveal_secret_signed`: the XDR encoding of
+    /// `(contract, order_id, owner, secret_hash, nonce)`
+    pub fn reveal_payload(env: Env, order_id: String, owner: Address, nonce: u64) -> Result<Bytes, Error> {
+        let escrow = Self::get_escrow(env.clone(), order_id.clone(), owner.clone())?;
+        Ok(Self::signed_reveal_payload(&env, order_id, owner, escrow.hash, nonce))
+    }
+
+    /// Reveals the secret with the taker's ed25519 signature instead of their authorization,
+    /// so a relayer can submit the transaction and pay its fees. Funds and the safety deposit
+    /// go to the taker.
+    pub fn reveal_secret_signed(
         env: Env,
-        caller: Address,
         order_id: String,
         owner: Address,
+        secret: Bytes,
+        nonce: u64,
+        signature: BytesN<64>,
     ) -> Result<(), Error> {
-        // Require authorization from the caller
-        caller.require_auth();
-        
+        Self::extend_instance_ttl(&env);
+
         // Get escrow
         let key = DataKey::Escrow(order_id.clone(), owner.clone());
-        let mut escrow: Escrow = env.storage().persistent()
+        let escrow: Escrow = env.storage().persistent()
             .get(&key)
             .ok_or(Error::EscrowNotFound)?;
 
@@ -251,65 +671,180 @@ impl AtomicSwapEscrowContract {
             return Err(Error::EscrowNotActive);
         }
 
-        // Only escrow owner or contract deployer can cancel
-        let contract_deployer = Address::from_string(&String::from_str(&env, "GDGFQGWD6DE6ZZO6F5SWBDB7N7RCYCW4B36IMNNLJKQHOYIKRSSVU6E2"));
-        if caller != escrow.owner && caller != contract_deployer {
-            return Err(Error::NotAuthorized);
+        // The signature must come from the taker's registered key, over the next nonce
+        let public_key: BytesN<32> = env.storage().persistent()
+            .get(&DataKey::RevealKey(escrow.taker.clone()))
+            .ok_or(Error::NoRevealKey)?;
+        let nonce_key = DataKey::RevealNonce(escrow.taker.clone());
+        let expected_nonce: u64 = env.storage().persistent().get(&nonce_key).unwrap_or(0);
+        if nonce != expected_nonce {
+            return Err(Error::InvalidNonce);
         }
 
-        // Check if timelock has expired
-        let current_time = env.ledger().timestamp();
-        if current_time < escrow.timelock {
-            return Err(Error::TimelockNotExpired);
+        let payload = Self::signed_reveal_payload(&env, order_id, owner, escrow.hash.clone(), nonce);
+        env.crypto().ed25519_verify(&public_key, &payload, &signature);
+
+        if env.ledger().timestamp() < escrow.withdrawal_at {
+            return Err(Error::WithdrawalNotStarted);
         }
 
-        // Transfer tokens back to owner
-        let contract_address = env.current_contract_address();
-        let transfer_args = soroban_sdk::vec![
-            &env, 
-            contract_address.into_val(&env), 
-            escrow.owner.into_val(&env), 
-            escrow.amount.into_val(&env)
-        ];
-        
-        // Fix: Add explicit type annotation for invoke_contract
-        let _result: () = env.invoke_contract(
-            &escrow.token_contract,
-            &Symbol::new(&env, "transfer"),
-            transfer_args,
-        );
+        env.storage().persistent().set(&nonce_key, &(nonce + 1));
+        env.storage().persistent().extend_ttl(&nonce_key, INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
 
-        // Update escrow status
-        escrow.status = EscrowStatus::Cancelled;
-        env.storage().persistent().set(&key, &escrow);
+        // The relayer isn't known to the contract, so the taker keeps the safety deposit
+        let taker = escrow.taker.clone();
+        Self::complete_escrow(&env, &key, escrow, secret, &taker)
+    }
 
-        // Emit EscrowCancelled event
-        let event = EscrowCancelledEvent {
-            order_id: order_id.clone(),
-            owner: owner.clone(),
-            amount: escrow.amount,
-        };
+    /// Cancels an escrow after timelock expiry, returning unfilled tokens to owner. The owner or admin
+    /// can cancel once the cancellation window opens, anyone once the public cancellation
+    /// window opens; the caller receives the safety deposit.
+    pub fn cancel_escrow(
+        env: Env,
+        caller: Address,
+        order_id: String,
+        owner: Address,
+    ) -> Result<(), Error> {
+        // Require authorization from the caller
+        caller.require_auth();
+        Self::extend_instance_ttl(&env);
+
+        Self::cancel(&env, &caller, order_id, owner)
+    }
+
+    /// Reveals the secrets of several escrows under one authorization, as `reveal_secret`
+    /// would for each. An order that fails is skipped rather than reverting the batch; the
+    /// result holds each order's outcome, in order. At most `MAX_BATCH_SIZE` orders.
+    pub fn reveal_many(
+        env: Env,
+        caller: Address,
+        reveals: Vec<RevealArgs>,
+    ) -> Result<Vec<BatchResult>, Error> {
+        caller.require_auth();
+        Self::extend_instance_ttl(&env);
+        if reveals.len() > MAX_BATCH_SIZE {
+            return Err(Error::BatchTooLarge);
+        }
+
+        let mut results = Vec::new(&env);
+        for args in reveals.iter() {
+            let result = Self::reveal(&env, &caller, args.order_id, args.owner, args.secret);
+            results.push_back(BatchResult::from(result));
+        }
+        Ok(results)
+    }
+
+    /// Cancels several `(order_id, owner)` escrows under one authorization, as
+    /// `cancel_escrow` would for each. An order that fails is skipped rather than reverting
+    /// the batch; the result holds each order's outcome, in order. At most `MAX_BATCH_SIZE`
+    /// orders.
+    pub fn cancel_many(
+        env: Env,
+        caller: Address,
+        escrows: Vec<(String, Address)>,
+    ) -> Result<Vec<BatchResult>, Error> {
+        caller.require_auth();
+        Self::extend_instance_ttl(&env);
+        if escrows.len() > MAX_BATCH_SIZE {
+            return Err(Error::BatchTooLarge);
+        }
+
+        let mut results = Vec::new(&env);
+        for (order_id, owner) in escrows.iter() {
+            results.push_back(BatchResult::from(Self::cancel(&env, &caller, order_id, owner)));
+        }
+        Ok(results)
+    }
 
-        env.events().publish((symbol_short!("cancelled"),), event);
+    /// Extends the storage TTL of an escrow so it stays live until past its timelock.
+    /// Anyone can call this, e.g. the taker of a long-running escrow.
+    pub fn extend_escrow_ttl(env: Env, order_id: String, owner: Address) -> Result<(), Error> {
+        Self::extend_instance_ttl(&env);
 
-        log!(&env, "Escrow cancelled: order_id={}, amount={}", order_id, escrow.amount);
+        let key = DataKey::Escrow(order_id, owner);
+        let escrow: Escrow = env.storage().persistent()
+            .get(&key)
+            .ok_or(Error::EscrowNotFound)?;
+
+        Self::extend_escrow_entry_ttl(&env, &key, escrow.public_cancellation_at);
         Ok(())
     }
 
     /// Checks if an escrow exists
     pub fn escrow_exists(env: Env, order_id: String, owner: Address) -> bool {
+        Self::extend_instance_ttl(&env);
         let key = DataKey::Escrow(order_id, owner);
         env.storage().persistent().has(&key)
     }
 
     /// Retrieves escrow details
     pub fn get_escrow(env: Env, order_id: String, owner: Address) -> Result<Escrow, Error> {
+        Self::extend_instance_ttl(&env);
         let key = DataKey::Escrow(order_id, owner);
         env.storage().persistent()
             .get(&key)
             .ok_or(Error::EscrowNotFound)
     }
 
+    /// Returns the secret revealed for a completed escrow
+    pub fn get_secret(env: Env, order_id: String, owner: Address) -> Result<Bytes, Error> {
+        let escrow = Self::get_escrow(env, order_id, owner)?;
+        escrow.secret.ok_or(Error::SecretNotRevealed)
+    }
+
+    /// Returns the secret revealed for the given hashlock, so watchers can complete the
+    /// counter-leg without knowing the order id
+    pub fn get_secret_by_hash(env: Env, hash: BytesN<32>) -> Result<Bytes, Error> {
+        Self::extend_instance_ttl(&env);
+        env.storage().persistent()
+            .get(&DataKey::Secret(hash))
+            .ok_or(Error::SecretNotRevealed)
+    }
+
+    /// Lists escrows created by `owner`, oldest first, starting at index `start`.
+    /// At most 50 escrows are returned per call. Index and escrow entries archived after
+    /// going untouched must be restored before they can be listed.
+    pub fn list_escrows_by_owner(env: Env, owner: Address, start: u32, limit: u32) -> Vec<Escrow> {
+        Self::extend_instance_ttl(&env);
+        let order_ids: Vec<String> = Self::read_index(
+            &env,
+            DataKey::OwnerCount(owner.clone()),
+            |chunk| DataKey::OwnerIndex(owner.clone(), chunk),
+            start,
+            limit,
+        );
+
+        let mut escrows = Vec::new(&env);
+        for order_id in order_ids.iter() {
+            if let Some(escrow) = env.storage().persistent().get(&DataKey::Escrow(order_id, owner.clone())) {
+                escrows.push_back(escrow);
+            }
+        }
+        escrows
+    }
+
+    /// Lists escrows naming `taker`, oldest first, starting at index `start`.
+    /// At most 50 escrows are returned per call. Index and escrow entries archived after
+    /// going untouched must be restored before they can be listed.
+    pub fn list_escrows_by_taker(env: Env, taker: Address, start: u32, limit: u32) -> Vec<Escrow> {
+        Self::extend_instance_ttl(&env);
+        let entries: Vec<(String, Address)> = Self::read_index(
+            &env,
+            DataKey::TakerCount(taker.clone()),
+            |chunk| DataKey::TakerIndex(taker.clone(), chunk),
+            start,
+            limit,
+        );
+
+        let mut escrows = Vec::new(&env);
+        for (order_id, owner) in entries.iter() {
+            if let Some(escrow) = env.storage().persistent().get(&DataKey::Escrow(order_id, owner)) {
+                escrows.push_back(escrow);
+            }
+        }
+        escrows
+    }
+
     /// Checks if an escrow is in active status
     pub fn is_escrow_active(env: Env, order_id: String, owner: Address) -> Result<bool, Error> {
         let escrow = Self::get_escrow(env, order_id, owner)?;
@@ -325,12 +860,375 @@ impl AtomicSwapEscrowContract {
 
     /// Utility function to get current ledger timestamp
     pub fn get_current_timestamp(env: Env) -> u64 {
+        Self::extend_instance_ttl(&env);
         env.ledger().timestamp()
     }
 
-    /// Utility function to compute SHA-256 hash of given data
-    pub fn compute_hash(env: Env, data: Bytes) -> Bytes {
-        env.crypto().sha256(&data).into()
+    /// Utility function to compute the SHA-256 or Keccak-256 hash of given data
+    pub fn compute_hash(env: Env, data: Bytes, hash_algo: HashAlgo) -> BytesN<32> {
+        Self::extend_instance_ttl(&env);
+        Self::hash_secret(&env, &data, hash_algo)
+    }
+}
+
+impl AtomicSwapEscrowContract {
+    fn extend_instance_ttl(env: &Env) {
+        env.storage()
+            .instance()
+            .extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+    }
+
+    /// Keeps an escrow entry live for the ledgers left until `until` plus a margin,
+    /// capped at the network's maximum TTL
+    fn extend_escrow_entry_ttl(env: &Env, key: &DataKey, until: u64) {
+        let ledgers_left = until.saturating_sub(env.ledger().timestamp()) / LEDGER_SECONDS;
+        let ttl = u32::try_from(ledgers_left)
+            .unwrap_or(u32::MAX)
+            .saturating_add(ESCROW_TTL_MARGIN)
+            .min(env.storage().max_ttl());
+        env.storage().persistent().extend_ttl(key, ttl, ttl);
+    }
+
+    fn extend_index_ttl(env: &Env, key: &DataKey) {
+        env.storage()
+            .persistent()
+            .extend_ttl(key, INDEX_LIFETIME_THRESHOLD, INDEX_BUMP_AMOUNT);
+    }
+
+    /// Appends an item to a chunked index, keeping the touched entries live at least as long
+    /// as the escrow
+    fn push_index<T>(
+        env: &Env,
+        count_key: DataKey,
+        chunk_key: impl Fn(u32) -> DataKey,
+        item: T,
+        until: u64,
+    ) where
+        T: IntoVal<Env, Val> + TryFromVal<Env, Val>,
+    {
+        let storage = env.storage().persistent();
+        let count: u32 = storage.get(&count_key).unwrap_or(0);
+        let key = chunk_key(count / INDEX_CHUNK_SIZE);
+
+        let mut chunk: Vec<T> = storage.get(&key).unwrap_or_else(|| Vec::new(env));
+        chunk.push_back(item);
+        storage.set(&key, &chunk);
+        storage.set(&count_key, &(count + 1));
+
+        Self::extend_escrow_entry_ttl(env, &key, until);
+        Self::extend_escrow_entry_ttl(env, &count_key, until);
+        Self::extend_index_ttl(env, &key);
+        Self::extend_index_ttl(env, &count_key);
+    }
+
+    /// Reads up to `limit` items (capped at MAX_PAGE_SIZE) from a chunked index, keeping the
+    /// entries read live
+    fn read_index<T>(
+        env: &Env,
+        count_key: DataKey,
+        chunk_key: impl Fn(u32) -> DataKey,
+        start: u32,
+        limit: u32,
+    ) -> Vec<T>
+    where
+        T: IntoVal<Env, Val> + TryFromVal<Env, Val>,
+    {
+        let storage = env.storage().persistent();
+        let Some(count) = storage.get::<_, u32>(&count_key) else {
+            return Vec::new(env);
+        };
+        Self::extend_index_ttl(env, &count_key);
+        let end = start.saturating_add(limit.min(MAX_PAGE_SIZE)).min(count);
+
+        let mut items = Vec::new(env);
+        let mut chunk: Option<(u32, Vec<T>)> = None;
+        for i in start..end {
+            let chunk_id = i / INDEX_CHUNK_SIZE;
+            if chunk.as_ref().map(|(id, _)| *id) != Some(chunk_id) {
+                let key = chunk_key(chunk_id);
+                let loaded: Vec<T> = storage.get(&key).unwrap_or_else(|| Vec::new(env));
+                if !loaded.is_empty() {
+                    Self::extend_index_ttl(env, &key);
+                }
+                chunk = Some((chunk_id, loaded));
+            }
+            if let Some(item) = chunk.as_ref().and_then(|(_, c)| c.get(i % INDEX_CHUNK_SIZE)) {
+                items.push_back(item);
+            }
+        }
+        items
+    }
+
+    /// Reveals an escrow's secret on behalf of `caller`, once its reveal window is open
+    fn reveal(
+        env: &Env,
+        caller: &Address,
+        order_id: String,
+        owner: Address,
+        secret: Bytes,
+    ) -> Result<(), Error> {
+        // Get escrow
+        let key = DataKey::Escrow(order_id, owner);
+        let escrow: Escrow = env.storage().persistent()
+            .get(&key)
+            .ok_or(Error::EscrowNotFound)?;
+
+        // Check if escrow is active
+        if escrow.status != EscrowStatus::Active {
+            return Err(Error::EscrowNotActive);
+        }
+
+        Self::check_reveal_window(env, &escrow, caller)?;
+        Self::complete_escrow(env, &key, escrow, secret, caller)
+    }
+
+    /// Cancels an escrow on behalf of `caller`, once its cancellation window is open
+    fn cancel(env: &Env, caller: &Address, order_id: String, owner: Address) -> Result<(), Error> {
+        // Get escrow
+        let key = DataKey::Escrow(order_id.clone(), owner.clone());
+        let mut escrow: Escrow = env.storage().persistent()
+            .get(&key)
+            .ok_or(Error::EscrowNotFound)?;
+
+        // Check if escrow is active
+        if escrow.status != EscrowStatus::Active {
+            return Err(Error::EscrowNotActive);
+        }
+
+        // Only escrow owner or admin can cancel, until the public cancellation window opens
+        let current_time = env.ledger().timestamp();
+        let privileged = *caller == escrow.owner || Self::is_admin(env, caller);
+        if !privileged && current_time < escrow.public_cancellation_at {
+            return Err(Error::NotAuthorized);
+        }
+
+        // Check if timelock has expired
+        if current_time < escrow.timelock {
+            return Err(Error::TimelockNotExpired);
+        }
+
+        // Transfer the unfilled tokens back to owner and the rest of the safety deposit to the caller
+        let refund = escrow.amount - escrow.filled;
+        token::Client::new(env, &escrow.token_contract).transfer(
+            &env.current_contract_address(),
+            &escrow.owner,
+            &refund,
+        );
+        let deposit_paid: i128 = (0..escrow.parts)
+            .filter(|i| escrow.filled_parts & (1 << i) != 0)
+            .map(|i| Self::tranche(escrow.safety_deposit, i, escrow.parts))
+            .sum();
+        Self::pay_safety_deposit(env, escrow.safety_deposit - deposit_paid, caller);
+
+        // Update escrow status
+        escrow.status = EscrowStatus::Cancelled;
+        env.storage().persistent().set(&key, &escrow);
+
+        // Emit EscrowCancelled event
+        let event = EscrowCancelledEvent {
+            order_id: order_id.clone(),
+            owner,
+            amount: refund,
+        };
+
+        env.events().publish(
+            (symbol_short!("escrow"), symbol_short!("cancelled"), escrow.hash, escrow.owner),
+            event,
+        );
+
+        log!(env, "Escrow cancelled: order_id={}, amount={}", order_id, refund);
+        Ok(())
+    }
+
+    /// Checks the timelock and hashlock, then pays out to the taker and publishes the secret
+    fn complete_escrow(
+        env: &Env,
+        key: &DataKey,
+        mut escrow: Escrow,
+        secret: Bytes,
+        finalizer: &Address,
+    ) -> Result<(), Error> {
+        // A partially fillable escrow's hash is a Merkle root, which the two child nodes
+        // concatenated would open in one go; its parts can only be revealed one by one
+        if escrow.parts != 0 {
+            return Err(Error::PartialFillRequired);
+        }
+
+        // Check if timelock has not expired
+        let current_time = env.ledger().timestamp();
+        if current_time >= escrow.timelock {
+            return Err(Error::TimelockExpired);
+        }
+
+        // Verify hash matches secret
+        let computed_hash = Self::hash_secret(env, &secret, escrow.hash_algo);
+        if computed_hash != escrow.hash {
+            return Err(Error::HashMismatch);
+        }
+
+        // Transfer tokens to taker and the safety deposit to the finalizer
+        token::Client::new(env, &escrow.token_contract).transfer(
+            &env.current_contract_address(),
+            &escrow.taker,
+            &escrow.amount,
+        );
+        Self::pay_safety_deposit(env, escrow.safety_deposit, finalizer);
+
+        // Update escrow status and record the secret for the counter-leg
+        escrow.status = EscrowStatus::Completed;
+        escrow.filled = escrow.amount;
+        escrow.secret = Some(secret.clone());
+        env.storage().persistent().set(key, &escrow);
+
+        let secret_key = DataKey::Secret(escrow.hash.clone());
+        env.storage().persistent().set(&secret_key, &secret);
+        env.storage().persistent().extend_ttl(&secret_key, ESCROW_TTL_MARGIN, ESCROW_TTL_MARGIN);
+
+        // Emit EscrowCompleted event
+        let event = EscrowCompletedEvent {
+            order_id: escrow.order_id.clone(),
+            owner: escrow.owner.clone(),
+            taker: escrow.taker.clone(),
+            amount: escrow.amount,
+            secret,
+        };
+
+        env.events().publish(
+            (symbol_short!("escrow"), symbol_short!("completed"), escrow.hash.clone(), escrow.taker.clone()),
+            event,
+        );
+
+        log!(env, "Escrow completed: order_id={}, amount={}", escrow.order_id, escrow.amount);
+        Ok(())
+    }
+
+    /// Decodes a stored escrow in any known layout into the current one
+    fn migrate_escrow(env: &Env, raw: Val) -> Option<Escrow> {
+        // Decoding a map whose fields don't match the layout traps, so only decode one with
+        // exactly the fields of a known layout
+        const V1_FIELDS: [&str; 14] = [
+            "order_id", "hash", "owner", "taker", "token_contract", "amount", "safety_deposit",
+            "withdrawal_at", "public_withdrawal_at", "timelock", "public_cancellation_at",
+            "status", "created_at", "secret",
+        ];
+        const V2_FIELDS: [&str; 1] = ["hash_algo"];
+        const V3_FIELDS: [&str; 3] = ["parts", "filled", "filled_parts"];
+
+        let fields = Map::<Symbol, Val>::try_from_val(env, &raw).ok()?;
+        let v2 = if Self::has_fields(env, &fields, &[&V1_FIELDS, &V2_FIELDS, &V3_FIELDS]) {
+            return Escrow::try_from_val(env, &raw).ok();
+        } else if Self::has_fields(env, &fields, &[&V1_FIELDS, &V2_FIELDS]) {
+            EscrowV2::try_from_val(env, &raw).ok()?
+        } else if Self::has_fields(env, &fields, &[&V1_FIELDS]) {
+            Self::migrate_escrow_v1(EscrowV1::try_from_val(env, &raw).ok()?)
+        } else {
+            return None;
+        };
+
+        let filled = if v2.status == EscrowStatus::Completed { v2.amount } else { 0 };
+        Some(Escrow {
+            order_id: v2.order_id,
+            hash: v2.hash,
+            hash_algo: v2.hash_algo,
+            owner: v2.owner,
+            taker: v2.taker,
+            token_contract: v2.token_contract,
+            amount: v2.amount,
+            safety_deposit: v2.safety_deposit,
+            withdrawal_at: v2.withdrawal_at,
+            public_withdrawal_at: v2.public_withdrawal_at,
+            timelock: v2.timelock,
+            public_cancellation_at: v2.public_cancellation_at,
+            status: v2.status,
+            created_at: v2.created_at,
+            secret: v2.secret,
+            parts: 0,
+            filled,
+            filled_parts: 0,
+        })
+    }
+
+    /// Whether a stored map has exactly the given groups of fields
+    fn has_fields(env: &Env, fields: &Map<Symbol, Val>, groups: &[&[&str]]) -> bool {
+        let count: usize = groups.iter().map(|names| names.len()).sum();
+        fields.len() as usize == count
+            && groups.iter().flat_map(|names| names.iter()).all(|name| fields.contains_key(Symbol::new(env, name)))
+    }
+
+    fn migrate_escrow_v1(v1: EscrowV1) -> EscrowV2 {
+        EscrowV2 {
+            order_id: v1.order_id,
+            hash: v1.hash,
+            hash_algo: HashAlgo::Sha256,
+            owner: v1.owner,
+            taker: v1.taker,
+            token_contract: v1.token_contract,
+            amount: v1.amount,
+            safety_deposit: v1.safety_deposit,
+            withdrawal_at: v1.withdrawal_at,
+            public_withdrawal_at: v1.public_withdrawal_at,
+            timelock: v1.timelock,
+            public_cancellation_at: v1.public_cancellation_at,
+            status: v1.status,
+            created_at: v1.created_at,
+            secret: v1.secret,
+        }
+    }
+
+    fn hash_secret(env: &Env, secret: &Bytes, hash_algo: HashAlgo) -> BytesN<32> {
+        match hash_algo {
+            HashAlgo::Sha256 => env.crypto().sha256(secret).into(),
+            HashAlgo::Keccak256 => env.crypto().keccak256(secret).into(),
+        }
+    }
+
+    fn pay_safety_deposit(env: &Env, amount: i128, to: &Address) {
+        if amount > 0 {
+            let native_token: Address = env.storage().instance().get(&DataKey::NativeToken).unwrap();
+            token::Client::new(env, &native_token).transfer(
+                &env.current_contract_address(),
+                to,
+                &amount,
+            );
+        }
+    }
+
+    /// Only taker or admin can reveal, until the public withdrawal window opens
+    fn check_reveal_window(env: &Env, escrow: &Escrow, caller: &Address) -> Result<(), Error> {
+        let current_time = env.ledger().timestamp();
+        let privileged = *caller == escrow.taker || Self::is_admin(env, caller);
+        if !privileged && current_time < escrow.public_withdrawal_at {
+            return Err(Error::NotAuthorized);
+        }
+        if current_time < escrow.withdrawal_at {
+            return Err(Error::WithdrawalNotStarted);
+        }
+        Ok(())
+    }
+
+    /// Share of `total` released by part `index`: floor(total * (index + 1) / parts) minus
+    /// floor(total * index / parts), so the tranches sum to exactly `total`
+    fn tranche(total: i128, index: u32, parts: u32) -> i128 {
+        let cumulative = |k: u32| {
+            let (k, n) = (k as i128, parts as i128);
+            total / n * k + total % n * k / n
+        };
+        cumulative(index + 1) - cumulative(index)
+    }
+
+    fn signed_reveal_payload(
+        env: &Env,
+        order_id: String,
+        owner: Address,
+        hash: BytesN<32>,
+        nonce: u64,
+    ) -> Bytes {
+        (env.current_contract_address(), order_id, owner, hash, nonce).to_xdr(env)
+    }
+
+    fn is_admin(env: &Env, address: &Address) -> bool {
+        let admin: Option<Address> = env.storage().instance().get(&DataKey::Admin);
+        admin.as_ref() == Some(address)
     }
 }
 
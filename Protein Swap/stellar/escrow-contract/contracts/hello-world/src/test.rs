@@ -0,0 +1,2240 @@
+#![cfg(test)]
+extern crate std;
+
+use super::*;
+use ed25519_dalek::{Signer, SigningKey};
+use soroban_env_host::storage::{AccessType, Footprint};
+use soroban_sdk::testutils::storage::{Instance as _, Persistent as _};
+use soroban_sdk::testutils::{Address as _, AuthorizedFunction, AuthorizedInvocation, Events, Ledger, MockAuth, MockAuthInvoke};
+use soroban_sdk::token::{StellarAssetClient, TokenClient};
+use soroban_sdk::{map, vec, Env, IntoVal, TryFromVal};
+
+const AMOUNT: i128 = 1000;
+const TIMELOCK: u64 = 3600;
+const PUBLIC_CANCELLATION_DELAY: u64 = 600;
+const SAFETY_DEPOSIT: i128 = 10;
+
+struct Setup<'a> {
+    env: Env,
+    client: AtomicSwapEscrowContractClient<'a>,
+    token: TokenClient<'a>,
+    native: TokenClient<'a>,
+    admin: Address,
+    owner: Address,
+    taker: Address,
+}
+
+fn setup<'a>() -> Setup<'a> {
+    let env = Env::default();
+    let contract_id = env.register_contract(None, AtomicSwapEscrowContract);
+    setup_contract(env, contract_id)
+}
+
+fn setup_contract<'a>(env: Env, contract_id: Address) -> Setup<'a> {
+    env.mock_all_auths();
+
+    let client = AtomicSwapEscrowContractClient::new(&env, &contract_id);
+
+    let token_admin = Address::generate(&env);
+    let token_id = env.register_stellar_asset_contract_v2(token_admin).address();
+    let token = TokenClient::new(&env, &token_id);
+    let native_id = env.register_stellar_asset_contract_v2(Address::generate(&env)).address();
+    let native = TokenClient::new(&env, &native_id);
+
+    let admin = Address::generate(&env);
+    let owner = Address::generate(&env);
+    let taker = Address::generate(&env);
+    StellarAssetClient::new(&env, &token_id).mint(&owner, &(AMOUNT * 10));
+    StellarAssetClient::new(&env, &native_id).mint(&owner, &(SAFETY_DEPOSIT * 100));
+    StellarAssetClient::new(&env, &native_id).mint(&taker, &(SAFETY_DEPOSIT * 100));
+
+    client.initialize(&admin, &native_id);
+
+    Setup {
+        env,
+        client,
+        token,
+        native,
+        admin,
+        owner,
+        taker,
+    }
+}
+
+// Taker-only reveals for the first half, then public reveals, then owner-only and public cancels
+fn timelocks(cancellation: u64) -> Timelocks {
+    Timelocks {
+        withdrawal: 0,
+        public_withdrawal: cancellation / 2,
+        cancellation,
+        public_cancellation: cancellation + PUBLIC_CANCELLATION_DELAY,
+    }
+}
+
+fn secret(env: &Env) -> Bytes {
+    Bytes::from_slice(env, &[1, 2, 3, 4, 5])
+}
+
+fn create_escrow(s: &Setup, order_id: &str) -> String {
+    let order_id = String::from_str(&s.env, order_id);
+    let hash = s.client.compute_hash(&secret(&s.env), &HashAlgo::Sha256);
+    s.client.create_escrow(
+        &s.owner,
+        &order_id,
+        &hash,
+        &HashAlgo::Sha256,
+        &s.taker,
+        &s.token.address,
+        &AMOUNT,
+        &timelocks(TIMELOCK),
+        &SAFETY_DEPOSIT,
+        &0,
+    );
+    order_id
+}
+
+fn try_create_escrow(
+    s: &Setup,
+    hash: &BytesN<32>,
+    amount: i128,
+    cancellation: u64,
+) -> Result<(), Error> {
+    let order_id = String::from_str(&s.env, "invalid");
+    match s.client.try_create_escrow(
+        &s.owner,
+        &order_id,
+        hash,
+        &HashAlgo::Sha256,
+        &s.taker,
+        &s.token.address,
+        &amount,
+        &timelocks(cancellation),
+        &SAFETY_DEPOSIT,
+        &0,
+    ) {
+        Ok(_) => Ok(()),
+        Err(err) => Err(err.unwrap()),
+    }
+}
+
+fn sign_reveal(s: &Setup, signer: &SigningKey, order_id: &String, nonce: u64) -> BytesN<64> {
+    let payload = s.client.reveal_payload(order_id, &s.owner, &nonce);
+    let payload: std::vec::Vec<u8> = payload.iter().collect();
+    BytesN::from_array(&s.env, &signer.sign(&payload).to_bytes())
+}
+
+fn register_reveal_key(s: &Setup) -> SigningKey {
+    let signer = SigningKey::from_bytes(&[7; 32]);
+    let public_key = BytesN::from_array(&s.env, &signer.verifying_key().to_bytes());
+    s.client.set_reveal_key(&s.taker, &public_key);
+    signer
+}
+
+fn escrow_ttl(s: &Setup, order_id: &String) -> u32 {
+    let key = DataKey::Escrow(order_id.clone(), s.owner.clone());
+    s.env.as_contract(&s.client.address, || s.env.storage().persistent().get_ttl(&key))
+}
+
+fn instance_ttl(s: &Setup) -> u32 {
+    s.env.as_contract(&s.client.address, || s.env.storage().instance().get_ttl())
+}
+
+#[test]
+fn test_initialize_sets_admin() {
+    let s = setup();
+
+    assert_eq!(s.client.get_admin(), s.admin);
+    assert_eq!(s.client.get_pending_admin(), None);
+}
+
+#[test]
+fn test_initialize_twice() {
+    let s = setup();
+    let other = Address::generate(&s.env);
+
+    assert_eq!(
+        s.client.try_initialize(&other, &s.native.address),
+        Err(Ok(Error::AlreadyInitialized))
+    );
+    assert_eq!(s.client.get_admin(), s.admin);
+}
+
+#[test]
+fn test_get_admin_not_initialized() {
+    let env = Env::default();
+    let contract_id = env.register_contract(None, AtomicSwapEscrowContract);
+    let client = AtomicSwapEscrowContractClient::new(&env, &contract_id);
+
+    assert_eq!(client.try_get_admin(), Err(Ok(Error::NotInitialized)));
+}
+
+#[test]
+fn test_admin_can_reveal_secret() {
+    let s = setup();
+    let order_id = create_escrow(&s, "admin_reveal");
+
+    s.client.reveal_secret(&s.admin, &order_id, &s.owner, &secret(&s.env));
+
+    assert_eq!(s.token.balance(&s.taker), AMOUNT);
+}
+
+#[test]
+fn test_admin_can_cancel_escrow() {
+    let s = setup();
+    let order_id = create_escrow(&s, "admin_cancel");
+    s.env.ledger().set_timestamp(TIMELOCK);
+
+    s.client.cancel_escrow(&s.admin, &order_id, &s.owner);
+
+    assert_eq!(s.token.balance(&s.owner), AMOUNT * 10);
+}
+
+#[test]
+fn test_stranger_cannot_reveal_or_cancel() {
+    let s = setup();
+    let order_id = create_escrow(&s, "stranger");
+    let stranger = Address::generate(&s.env);
+
+    assert_eq!(
+        s.client.try_reveal_secret(&stranger, &order_id, &s.owner, &secret(&s.env)),
+        Err(Ok(Error::NotAuthorized))
+    );
+    s.env.ledger().set_timestamp(TIMELOCK);
+    assert_eq!(
+        s.client.try_cancel_escrow(&stranger, &order_id, &s.owner),
+        Err(Ok(Error::NotAuthorized))
+    );
+}
+
+#[test]
+fn test_admin_rotation() {
+    let s = setup();
+    let new_admin = Address::generate(&s.env);
+
+    s.client.set_admin(&new_admin);
+    assert_eq!(s.env.auths().pop().unwrap().0, s.admin);
+    assert_eq!(s.client.get_pending_admin(), Some(new_admin.clone()));
+    // The old admin stays in charge until the new one accepts
+    assert_eq!(s.client.get_admin(), s.admin);
+
+    // Accepting takes the pending admin's own signature
+    s.client.accept_admin();
+    assert_eq!(
+        s.env.auths(),
+        std::vec![(
+            new_admin.clone(),
+            AuthorizedInvocation {
+                function: AuthorizedFunction::Contract((
+                    s.client.address.clone(),
+                    Symbol::new(&s.env, "accept_admin"),
+                    Vec::new(&s.env),
+                )),
+                sub_invocations: std::vec![],
+            }
+        )]
+    );
+    assert_eq!(s.client.get_admin(), new_admin);
+    assert_eq!(s.client.get_pending_admin(), None);
+
+    let order_id = create_escrow(&s, "rotation");
+    assert_eq!(
+        s.client.try_reveal_secret(&s.admin, &order_id, &s.owner, &secret(&s.env)),
+        Err(Ok(Error::NotAuthorized))
+    );
+    s.client.reveal_secret(&new_admin, &order_id, &s.owner, &secret(&s.env));
+}
+
+#[test]
+fn test_accept_admin_without_pending() {
+    let s = setup();
+
+    assert_eq!(s.client.try_accept_admin(), Err(Ok(Error::NoPendingAdmin)));
+}
+
+#[test]
+fn test_create_escrow_extends_ttl_past_timelock() {
+    let s = setup();
+    let order_id = create_escrow(&s, "ttl_create");
+
+    let expected = ((TIMELOCK + PUBLIC_CANCELLATION_DELAY) / LEDGER_SECONDS) as u32 + ESCROW_TTL_MARGIN;
+    assert_eq!(escrow_ttl(&s, &order_id), expected);
+}
+
+#[test]
+fn test_long_timelock_ttl_capped_at_max() {
+    let s = setup();
+    s.env.ledger().set_max_entry_ttl(DAY_IN_LEDGERS);
+
+    let order_id = create_escrow(&s, "ttl_long");
+
+    let max_ttl = s.env.as_contract(&s.client.address, || s.env.storage().max_ttl());
+    assert_eq!(escrow_ttl(&s, &order_id), max_ttl);
+}
+
+#[test]
+fn test_extend_escrow_ttl() {
+    let s = setup();
+    let order_id = create_escrow(&s, "ttl_extend");
+    let initial = escrow_ttl(&s, &order_id);
+
+    // Half the timelock passes without anyone touching the escrow
+    let elapsed_ledgers = (TIMELOCK / 2 / LEDGER_SECONDS) as u32;
+    s.env.ledger().with_mut(|li| {
+        li.timestamp += TIMELOCK / 2;
+        li.sequence_number += elapsed_ledgers;
+    });
+    assert_eq!(escrow_ttl(&s, &order_id), initial - elapsed_ledgers);
+
+    // Anyone can top it back up to the remaining windows plus the margin
+    s.env.ledger().with_mut(|li| li.sequence_number += ESCROW_TTL_MARGIN / 2);
+    s.client.extend_escrow_ttl(&order_id, &s.owner);
+    let remaining = elapsed_ledgers + (PUBLIC_CANCELLATION_DELAY / LEDGER_SECONDS) as u32;
+    assert_eq!(escrow_ttl(&s, &order_id), remaining + ESCROW_TTL_MARGIN);
+}
+
+#[test]
+fn test_extend_escrow_ttl_not_found() {
+    let s = setup();
+    let order_id = String::from_str(&s.env, "missing");
+
+    assert_eq!(
+        s.client.try_extend_escrow_ttl(&order_id, &s.owner),
+        Err(Ok(Error::EscrowNotFound))
+    );
+}
+
+#[test]
+fn test_entry_points_extend_instance_ttl() {
+    let s = setup();
+    assert_eq!(instance_ttl(&s), INSTANCE_BUMP_AMOUNT);
+
+    s.env.ledger().with_mut(|li| li.sequence_number += 2 * DAY_IN_LEDGERS);
+    assert_eq!(instance_ttl(&s), INSTANCE_BUMP_AMOUNT - 2 * DAY_IN_LEDGERS);
+
+    s.client.get_current_timestamp();
+    assert_eq!(instance_ttl(&s), INSTANCE_BUMP_AMOUNT);
+}
+
+#[test]
+fn test_secret_stored_on_reveal() {
+    let s = setup();
+    let order_id = create_escrow(&s, "secret_stored");
+    let hash = s.client.compute_hash(&secret(&s.env), &HashAlgo::Sha256);
+
+    assert_eq!(
+        s.client.try_get_secret(&order_id, &s.owner),
+        Err(Ok(Error::SecretNotRevealed))
+    );
+    assert_eq!(s.client.try_get_secret_by_hash(&hash), Err(Ok(Error::SecretNotRevealed)));
+
+    s.client.reveal_secret(&s.taker, &order_id, &s.owner, &secret(&s.env));
+
+    assert_eq!(s.client.get_secret(&order_id, &s.owner), secret(&s.env));
+    assert_eq!(s.client.get_secret_by_hash(&hash), secret(&s.env));
+    assert_eq!(s.client.get_escrow(&order_id, &s.owner).secret, Some(secret(&s.env)));
+}
+
+#[test]
+fn test_completed_event_includes_secret() {
+    let s = setup();
+    let order_id = create_escrow(&s, "secret_event");
+
+    s.client.reveal_secret(&s.taker, &order_id, &s.owner, &secret(&s.env));
+
+    let (_, _, data) = s.env.events().all().last().unwrap();
+    let event = EscrowCompletedEvent::try_from_val(&s.env, &data).unwrap();
+    assert_eq!(event.order_id, order_id);
+    assert_eq!(event.secret, secret(&s.env));
+}
+
+#[test]
+fn test_cancelled_escrow_has_no_secret() {
+    let s = setup();
+    let order_id = create_escrow(&s, "secret_cancelled");
+    s.env.ledger().set_timestamp(TIMELOCK);
+
+    s.client.cancel_escrow(&s.owner, &order_id, &s.owner);
+
+    assert_eq!(
+        s.client.try_get_secret(&order_id, &s.owner),
+        Err(Ok(Error::SecretNotRevealed))
+    );
+}
+
+#[test]
+fn test_create_escrow_zero_amount() {
+    let s = setup();
+    let hash = s.client.compute_hash(&secret(&s.env), &HashAlgo::Sha256);
+
+    assert_eq!(try_create_escrow(&s, &hash, 0, TIMELOCK), Err(Error::InvalidAmount));
+}
+
+#[test]
+fn test_create_escrow_negative_amount() {
+    let s = setup();
+    let hash = s.client.compute_hash(&secret(&s.env), &HashAlgo::Sha256);
+
+    assert_eq!(try_create_escrow(&s, &hash, -AMOUNT, TIMELOCK), Err(Error::InvalidAmount));
+    assert_eq!(s.token.balance(&s.owner), AMOUNT * 10);
+}
+
+#[test]
+fn test_create_escrow_zero_hash() {
+    let s = setup();
+    let hash = BytesN::from_array(&s.env, &[0; 32]);
+
+    assert_eq!(try_create_escrow(&s, &hash, AMOUNT, TIMELOCK), Err(Error::InvalidHash));
+}
+
+#[test]
+fn test_create_escrow_timelock_bounds() {
+    let s = setup();
+    let hash = s.client.compute_hash(&secret(&s.env), &HashAlgo::Sha256);
+
+    assert_eq!(
+        try_create_escrow(&s, &hash, AMOUNT, MIN_TIMELOCK_DURATION - 1),
+        Err(Error::InvalidTimelock)
+    );
+    assert_eq!(
+        try_create_escrow(&s, &hash, AMOUNT, MAX_TIMELOCK_DURATION + 1),
+        Err(Error::InvalidTimelock)
+    );
+    assert_eq!(try_create_escrow(&s, &hash, AMOUNT, MIN_TIMELOCK_DURATION), Ok(()));
+}
+
+#[test]
+fn test_create_escrow_timelock_overflow() {
+    let s = setup();
+    let hash = s.client.compute_hash(&secret(&s.env), &HashAlgo::Sha256);
+    s.env.ledger().set_timestamp(u64::MAX - TIMELOCK / 2);
+
+    assert_eq!(try_create_escrow(&s, &hash, AMOUNT, TIMELOCK), Err(Error::InvalidTimelock));
+}
+
+#[test]
+fn test_cross_asset_swap_with_stellar_asset_contracts() {
+    let s = setup();
+
+    // Two Stellar Asset Contracts standing in for native XLM and a classic asset
+    let xlm = s.env.register_stellar_asset_contract_v2(Address::generate(&s.env));
+    let usdc = s.env.register_stellar_asset_contract_v2(Address::generate(&s.env));
+    let xlm_token = TokenClient::new(&s.env, &xlm.address());
+    let usdc_token = TokenClient::new(&s.env, &usdc.address());
+    StellarAssetClient::new(&s.env, &xlm.address()).mint(&s.owner, &AMOUNT);
+    StellarAssetClient::new(&s.env, &usdc.address()).mint(&s.taker, &(AMOUNT * 2));
+
+    // Each side locks its asset for the other under the same hashlock
+    let order_id = String::from_str(&s.env, "cross_asset");
+    let hash = s.client.compute_hash(&secret(&s.env), &HashAlgo::Sha256);
+    s.client.create_escrow(
+        &s.owner,
+        &order_id,
+        &hash,
+        &HashAlgo::Sha256,
+        &s.taker,
+        &xlm.address(),
+        &AMOUNT,
+        &timelocks(TIMELOCK),
+        &SAFETY_DEPOSIT,
+        &0,
+    );
+    s.client.create_escrow(
+        &s.taker,
+        &order_id,
+        &hash,
+        &HashAlgo::Sha256,
+        &s.owner,
+        &usdc.address(),
+        &(AMOUNT * 2),
+        &timelocks(TIMELOCK),
+        &SAFETY_DEPOSIT,
+        &0,
+    );
+    assert_eq!(xlm_token.balance(&s.client.address), AMOUNT);
+    assert_eq!(usdc_token.balance(&s.client.address), AMOUNT * 2);
+
+    // The taker claims the XLM, and the owner reuses the published secret to claim the USDC
+    s.client.reveal_secret(&s.taker, &order_id, &s.owner, &secret(&s.env));
+    let revealed = s.client.get_secret_by_hash(&hash);
+    s.client.reveal_secret(&s.owner, &order_id, &s.taker, &revealed);
+
+    assert_eq!(xlm_token.balance(&s.taker), AMOUNT);
+    assert_eq!(usdc_token.balance(&s.owner), AMOUNT * 2);
+    assert_eq!(xlm_token.balance(&s.client.address), 0);
+    assert_eq!(usdc_token.balance(&s.client.address), 0);
+}
+
+#[test]
+fn test_create_escrow_insufficient_balance() {
+    let s = setup();
+    let hash = s.client.compute_hash(&secret(&s.env), &HashAlgo::Sha256);
+    let order_id = String::from_str(&s.env, "insufficient");
+
+    // The token contract rejects the transfer, so no escrow is recorded
+    let result = s.client.try_create_escrow(
+        &s.owner,
+        &order_id,
+        &hash,
+        &HashAlgo::Sha256,
+        &s.taker,
+        &s.token.address,
+        &(AMOUNT * 11),
+        &timelocks(TIMELOCK),
+        &SAFETY_DEPOSIT,
+        &0,
+    );
+    assert!(result.is_err());
+    assert!(!s.client.escrow_exists(&order_id, &s.owner));
+}
+
+#[test]
+fn test_create_escrow_success() {
+    let s = setup();
+    let order_id = create_escrow(&s, "test_order_001");
+
+    assert!(s.client.escrow_exists(&order_id, &s.owner));
+    assert!(s.client.is_escrow_active(&order_id, &s.owner));
+    assert_eq!(s.token.balance(&s.owner), AMOUNT * 9);
+    assert_eq!(s.token.balance(&s.client.address), AMOUNT);
+}
+
+#[test]
+fn test_create_escrow_already_exists() {
+    let s = setup();
+    let order_id = create_escrow(&s, "test_order_001");
+    let hash = s.client.compute_hash(&secret(&s.env), &HashAlgo::Sha256);
+
+    assert_eq!(
+        s.client.try_create_escrow(
+            &s.owner,
+            &order_id,
+            &hash,
+            &HashAlgo::Sha256,
+            &s.taker,
+            &s.token.address,
+            &AMOUNT,
+            &timelocks(TIMELOCK),
+            &SAFETY_DEPOSIT,
+            &0,
+        ),
+        Err(Ok(Error::EscrowAlreadyExists))
+    );
+    assert_eq!(s.token.balance(&s.client.address), AMOUNT);
+}
+
+#[test]
+fn test_escrow_data_integrity() {
+    let s = setup();
+    s.env.ledger().set_timestamp(1_000);
+    let order_id = create_escrow(&s, "test_order_001");
+
+    let escrow = s.client.get_escrow(&order_id, &s.owner);
+    assert_eq!(escrow.order_id, order_id);
+    assert_eq!(escrow.hash, s.client.compute_hash(&secret(&s.env), &HashAlgo::Sha256));
+    assert_eq!(escrow.owner, s.owner);
+    assert_eq!(escrow.taker, s.taker);
+    assert_eq!(escrow.token_contract, s.token.address);
+    assert_eq!(escrow.amount, AMOUNT);
+    assert_eq!(escrow.timelock, 1_000 + TIMELOCK);
+    assert_eq!(escrow.status, EscrowStatus::Active);
+    assert_eq!(escrow.created_at, 1_000);
+    assert_eq!(escrow.secret, None);
+}
+
+#[test]
+fn test_reveal_secret_success() {
+    let s = setup();
+    let order_id = create_escrow(&s, "test_order_001");
+
+    s.client.reveal_secret(&s.taker, &order_id, &s.owner, &secret(&s.env));
+
+    assert_eq!(s.token.balance(&s.taker), AMOUNT);
+    assert_eq!(s.token.balance(&s.client.address), 0);
+    assert_eq!(
+        s.client.get_escrow(&order_id, &s.owner).status,
+        EscrowStatus::Completed
+    );
+}
+
+#[test]
+fn test_reveal_secret_escrow_not_found() {
+    let s = setup();
+    let order_id = String::from_str(&s.env, "missing");
+
+    assert_eq!(
+        s.client.try_reveal_secret(&s.taker, &order_id, &s.owner, &secret(&s.env)),
+        Err(Ok(Error::EscrowNotFound))
+    );
+}
+
+#[test]
+fn test_reveal_secret_wrong_caller() {
+    let s = setup();
+    let order_id = create_escrow(&s, "test_order_001");
+
+    // The owner can't claim their own escrow, even with the right secret
+    assert_eq!(
+        s.client.try_reveal_secret(&s.owner, &order_id, &s.owner, &secret(&s.env)),
+        Err(Ok(Error::NotAuthorized))
+    );
+    assert!(s.client.is_escrow_active(&order_id, &s.owner));
+}
+
+#[test]
+fn test_reveal_secret_wrong_hash() {
+    let s = setup();
+    let order_id = create_escrow(&s, "test_order_001");
+    let wrong_secret = Bytes::from_slice(&s.env, &[9, 9, 9]);
+
+    assert_eq!(
+        s.client.try_reveal_secret(&s.taker, &order_id, &s.owner, &wrong_secret),
+        Err(Ok(Error::HashMismatch))
+    );
+    assert_eq!(s.token.balance(&s.taker), 0);
+}
+
+#[test]
+fn test_reveal_secret_timelock_expired() {
+    let s = setup();
+    let order_id = create_escrow(&s, "test_order_001");
+    s.env.ledger().set_timestamp(TIMELOCK);
+
+    assert_eq!(
+        s.client.try_reveal_secret(&s.taker, &order_id, &s.owner, &secret(&s.env)),
+        Err(Ok(Error::TimelockExpired))
+    );
+}
+
+#[test]
+fn test_cancel_escrow_success() {
+    let s = setup();
+    let order_id = create_escrow(&s, "test_order_001");
+    s.env.ledger().set_timestamp(TIMELOCK);
+
+    s.client.cancel_escrow(&s.owner, &order_id, &s.owner);
+
+    assert_eq!(s.token.balance(&s.owner), AMOUNT * 10);
+    assert_eq!(s.token.balance(&s.client.address), 0);
+    assert_eq!(
+        s.client.get_escrow(&order_id, &s.owner).status,
+        EscrowStatus::Cancelled
+    );
+}
+
+#[test]
+fn test_cancel_escrow_not_found() {
+    let s = setup();
+    let order_id = String::from_str(&s.env, "missing");
+
+    assert_eq!(
+        s.client.try_cancel_escrow(&s.owner, &order_id, &s.owner),
+        Err(Ok(Error::EscrowNotFound))
+    );
+}
+
+#[test]
+fn test_cancel_escrow_wrong_caller() {
+    let s = setup();
+    let order_id = create_escrow(&s, "test_order_001");
+    s.env.ledger().set_timestamp(TIMELOCK);
+
+    // The taker can't refund the owner's escrow
+    assert_eq!(
+        s.client.try_cancel_escrow(&s.taker, &order_id, &s.owner),
+        Err(Ok(Error::NotAuthorized))
+    );
+}
+
+#[test]
+fn test_cancel_escrow_timelock_not_expired() {
+    let s = setup();
+    let order_id = create_escrow(&s, "test_order_001");
+    s.env.ledger().set_timestamp(TIMELOCK - 1);
+
+    assert_eq!(
+        s.client.try_cancel_escrow(&s.owner, &order_id, &s.owner),
+        Err(Ok(Error::TimelockNotExpired))
+    );
+}
+
+#[test]
+fn test_cancel_completed_escrow() {
+    let s = setup();
+    let order_id = create_escrow(&s, "test_order_001");
+    s.client.reveal_secret(&s.taker, &order_id, &s.owner, &secret(&s.env));
+    s.env.ledger().set_timestamp(TIMELOCK);
+
+    assert_eq!(
+        s.client.try_cancel_escrow(&s.owner, &order_id, &s.owner),
+        Err(Ok(Error::EscrowNotActive))
+    );
+    assert_eq!(s.token.balance(&s.taker), AMOUNT);
+}
+
+#[test]
+fn test_operations_on_completed_escrow() {
+    let s = setup();
+    let order_id = create_escrow(&s, "test_order_001");
+    s.client.reveal_secret(&s.taker, &order_id, &s.owner, &secret(&s.env));
+
+    assert_eq!(
+        s.client.try_reveal_secret(&s.taker, &order_id, &s.owner, &secret(&s.env)),
+        Err(Ok(Error::EscrowNotActive))
+    );
+    assert!(!s.client.is_escrow_active(&order_id, &s.owner));
+
+    // A completed order id can't be reused by the same owner
+    let hash = s.client.compute_hash(&secret(&s.env), &HashAlgo::Sha256);
+    assert_eq!(
+        s.client.try_create_escrow(
+            &s.owner,
+            &order_id,
+            &hash,
+            &HashAlgo::Sha256,
+            &s.taker,
+            &s.token.address,
+            &AMOUNT,
+            &timelocks(TIMELOCK),
+            &SAFETY_DEPOSIT,
+            &0,
+        ),
+        Err(Ok(Error::EscrowAlreadyExists))
+    );
+}
+
+#[test]
+fn test_operations_on_cancelled_escrow() {
+    let s = setup();
+    let order_id = create_escrow(&s, "test_order_001");
+    s.env.ledger().set_timestamp(TIMELOCK);
+    s.client.cancel_escrow(&s.owner, &order_id, &s.owner);
+
+    assert_eq!(
+        s.client.try_cancel_escrow(&s.owner, &order_id, &s.owner),
+        Err(Ok(Error::EscrowNotActive))
+    );
+    assert_eq!(
+        s.client.try_reveal_secret(&s.taker, &order_id, &s.owner, &secret(&s.env)),
+        Err(Ok(Error::EscrowNotActive))
+    );
+    assert_eq!(s.token.balance(&s.owner), AMOUNT * 10);
+}
+
+#[test]
+fn test_escrow_lifecycle_completed() {
+    let s = setup();
+    let order_id = create_escrow(&s, "test_order_001");
+    assert!(s.client.is_escrow_active(&order_id, &s.owner));
+    assert!(!s.client.is_timelock_expired(&order_id, &s.owner));
+
+    s.env.ledger().set_timestamp(TIMELOCK / 2);
+    s.client.reveal_secret(&s.taker, &order_id, &s.owner, &secret(&s.env));
+
+    assert!(!s.client.is_escrow_active(&order_id, &s.owner));
+    assert_eq!(s.token.balance(&s.owner), AMOUNT * 9);
+    assert_eq!(s.token.balance(&s.taker), AMOUNT);
+}
+
+#[test]
+fn test_escrow_lifecycle_cancelled() {
+    let s = setup();
+    let order_id = create_escrow(&s, "test_order_001");
+
+    s.env.ledger().set_timestamp(TIMELOCK + 1);
+    assert!(s.client.is_timelock_expired(&order_id, &s.owner));
+    s.client.cancel_escrow(&s.owner, &order_id, &s.owner);
+
+    assert!(!s.client.is_escrow_active(&order_id, &s.owner));
+    assert_eq!(s.token.balance(&s.owner), AMOUNT * 10);
+    assert_eq!(s.token.balance(&s.taker), 0);
+}
+
+#[test]
+fn test_escrow_state_transitions() {
+    let s = setup();
+    let completed = create_escrow(&s, "test_order_001");
+    let cancelled = create_escrow(&s, "test_order_002");
+    let status = |order_id: &String| s.client.get_escrow(order_id, &s.owner).status;
+    assert_eq!(status(&completed), EscrowStatus::Active);
+    assert_eq!(status(&cancelled), EscrowStatus::Active);
+
+    s.client.reveal_secret(&s.taker, &completed, &s.owner, &secret(&s.env));
+    assert_eq!(status(&completed), EscrowStatus::Completed);
+    assert_eq!(status(&cancelled), EscrowStatus::Active);
+
+    s.env.ledger().set_timestamp(TIMELOCK);
+    s.client.cancel_escrow(&s.owner, &cancelled, &s.owner);
+    assert_eq!(status(&completed), EscrowStatus::Completed);
+    assert_eq!(status(&cancelled), EscrowStatus::Cancelled);
+}
+
+#[test]
+fn test_complete_atomic_swap_flow() {
+    let s = setup();
+    let order_id = create_escrow(&s, "test_order_001");
+
+    // The taker learns the secret off-chain once the counter-leg is locked
+    s.env.ledger().set_timestamp(60);
+    s.client.reveal_secret(&s.taker, &order_id, &s.owner, &secret(&s.env));
+
+    // Anyone watching can now read the secret to settle the other chain
+    let hash = s.client.compute_hash(&secret(&s.env), &HashAlgo::Sha256);
+    assert_eq!(s.client.get_secret_by_hash(&hash), secret(&s.env));
+    assert_eq!(s.token.balance(&s.taker), AMOUNT);
+    assert_eq!(s.token.balance(&s.owner), AMOUNT * 9);
+}
+
+#[test]
+fn test_timelock_cancellation_flow() {
+    let s = setup();
+    let order_id = create_escrow(&s, "test_order_001");
+
+    // Nobody reveals before the timelock, so the owner takes the funds back
+    s.env.ledger().set_timestamp(TIMELOCK - 1);
+    assert_eq!(
+        s.client.try_cancel_escrow(&s.owner, &order_id, &s.owner),
+        Err(Ok(Error::TimelockNotExpired))
+    );
+
+    s.env.ledger().set_timestamp(TIMELOCK);
+    s.client.cancel_escrow(&s.owner, &order_id, &s.owner);
+    assert_eq!(
+        s.client.try_reveal_secret(&s.taker, &order_id, &s.owner, &secret(&s.env)),
+        Err(Ok(Error::EscrowNotActive))
+    );
+    assert_eq!(s.token.balance(&s.owner), AMOUNT * 10);
+}
+
+#[test]
+fn test_timelock_behavior() {
+    let s = setup();
+    let order_id = create_escrow(&s, "test_order_001");
+
+    s.env.ledger().set_timestamp(TIMELOCK - 1);
+    assert!(!s.client.is_timelock_expired(&order_id, &s.owner));
+
+    // The timelock is exclusive for reveals and inclusive for cancels
+    s.env.ledger().set_timestamp(TIMELOCK);
+    assert!(s.client.is_timelock_expired(&order_id, &s.owner));
+    assert_eq!(
+        s.client.try_reveal_secret(&s.taker, &order_id, &s.owner, &secret(&s.env)),
+        Err(Ok(Error::TimelockExpired))
+    );
+    s.client.cancel_escrow(&s.owner, &order_id, &s.owner);
+}
+
+#[test]
+fn test_hash_verification() {
+    let s = setup();
+    let order_id = create_escrow(&s, "test_order_001");
+
+    // Secrets that differ by one byte, or are a prefix, don't match the hashlock
+    for wrong in [&[1u8, 2, 3, 4, 6][..], &[1, 2, 3, 4][..], &[][..]] {
+        let wrong = Bytes::from_slice(&s.env, wrong);
+        assert_eq!(
+            s.client.try_reveal_secret(&s.taker, &order_id, &s.owner, &wrong),
+            Err(Ok(Error::HashMismatch))
+        );
+    }
+    s.client.reveal_secret(&s.taker, &order_id, &s.owner, &secret(&s.env));
+}
+
+#[test]
+fn test_multiple_escrows_same_owner() {
+    let s = setup();
+    let first = create_escrow(&s, "test_order_001");
+    let second = create_escrow(&s, "test_order_002");
+    let third = create_escrow(&s, "test_order_003");
+    assert_eq!(s.token.balance(&s.client.address), AMOUNT * 3);
+
+    s.client.reveal_secret(&s.taker, &second, &s.owner, &secret(&s.env));
+
+    assert!(s.client.is_escrow_active(&first, &s.owner));
+    assert!(!s.client.is_escrow_active(&second, &s.owner));
+    assert!(s.client.is_escrow_active(&third, &s.owner));
+    assert_eq!(s.token.balance(&s.client.address), AMOUNT * 2);
+}
+
+#[test]
+fn test_different_owners_same_order_id() {
+    let s = setup();
+    let other_owner = Address::generate(&s.env);
+    StellarAssetClient::new(&s.env, &s.token.address).mint(&other_owner, &AMOUNT);
+    StellarAssetClient::new(&s.env, &s.native.address).mint(&other_owner, &SAFETY_DEPOSIT);
+
+    // Escrows are keyed by (order_id, owner), so both owners can use the same id
+    let order_id = create_escrow(&s, "test_order_001");
+    let hash = s.client.compute_hash(&secret(&s.env), &HashAlgo::Sha256);
+    s.client.create_escrow(
+        &other_owner,
+        &order_id,
+        &hash,
+        &HashAlgo::Sha256,
+        &s.taker,
+        &s.token.address,
+        &AMOUNT,
+        &timelocks(TIMELOCK),
+        &SAFETY_DEPOSIT,
+        &0,
+    );
+
+    s.client.reveal_secret(&s.taker, &order_id, &other_owner, &secret(&s.env));
+
+    assert!(s.client.is_escrow_active(&order_id, &s.owner));
+    assert!(!s.client.is_escrow_active(&order_id, &other_owner));
+    assert_eq!(s.token.balance(&other_owner), 0);
+    assert_eq!(s.token.balance(&s.owner), AMOUNT * 9);
+}
+
+#[test]
+fn test_timestamp_and_hash_utilities() {
+    let s = setup();
+    assert_eq!(s.client.get_current_timestamp(), 0);
+
+    s.env.ledger().set_timestamp(12_345);
+    assert_eq!(s.client.get_current_timestamp(), 12_345);
+
+    let hash = s.client.compute_hash(&secret(&s.env), &HashAlgo::Sha256);
+    let expected: BytesN<32> = s.env.crypto().sha256(&secret(&s.env)).into();
+    assert_eq!(hash, expected);
+}
+
+#[test]
+fn test_utility_functions() {
+    let s = setup();
+    let order_id = String::from_str(&s.env, "test_order_001");
+
+    assert!(!s.client.escrow_exists(&order_id, &s.owner));
+    assert_eq!(
+        s.client.try_get_escrow(&order_id, &s.owner),
+        Err(Ok(Error::EscrowNotFound))
+    );
+    assert_eq!(
+        s.client.try_is_escrow_active(&order_id, &s.owner),
+        Err(Ok(Error::EscrowNotFound))
+    );
+    assert_eq!(
+        s.client.try_is_timelock_expired(&order_id, &s.owner),
+        Err(Ok(Error::EscrowNotFound))
+    );
+
+    create_escrow(&s, "test_order_001");
+    assert!(s.client.escrow_exists(&order_id, &s.owner));
+    assert!(s.client.is_escrow_active(&order_id, &s.owner));
+    assert!(!s.client.is_timelock_expired(&order_id, &s.owner));
+}
+
+#[test]
+fn test_list_escrows_by_owner() {
+    let s = setup();
+    let first = create_escrow(&s, "test_order_001");
+    let second = create_escrow(&s, "test_order_002");
+    let third = create_escrow(&s, "test_order_003");
+
+    let all = s.client.list_escrows_by_owner(&s.owner, &0, &10);
+    assert_eq!(all.len(), 3);
+    assert_eq!(all.get(0).unwrap().order_id, first);
+    assert_eq!(all.get(1).unwrap().order_id, second);
+    assert_eq!(all.get(2).unwrap().order_id, third);
+
+    let page = s.client.list_escrows_by_owner(&s.owner, &1, &1);
+    assert_eq!(page.len(), 1);
+    assert_eq!(page.get(0).unwrap().order_id, second);
+
+    assert_eq!(s.client.list_escrows_by_owner(&s.owner, &3, &10).len(), 0);
+    assert_eq!(s.client.list_escrows_by_owner(&s.taker, &0, &10).len(), 0);
+}
+
+#[test]
+fn test_list_escrows_by_taker_across_owners() {
+    let s = setup();
+    let other_owner = Address::generate(&s.env);
+    StellarAssetClient::new(&s.env, &s.token.address).mint(&other_owner, &AMOUNT);
+    StellarAssetClient::new(&s.env, &s.native.address).mint(&other_owner, &SAFETY_DEPOSIT);
+
+    let order_id = create_escrow(&s, "test_order_001");
+    let hash = s.client.compute_hash(&secret(&s.env), &HashAlgo::Sha256);
+    s.client.create_escrow(
+        &other_owner,
+        &order_id,
+        &hash,
+        &HashAlgo::Sha256,
+        &s.taker,
+        &s.token.address,
+        &AMOUNT,
+        &timelocks(TIMELOCK),
+        &SAFETY_DEPOSIT,
+        &0,
+    );
+
+    // Listing reflects the current escrow state
+    s.client.reveal_secret(&s.taker, &order_id, &s.owner, &secret(&s.env));
+
+    let escrows = s.client.list_escrows_by_taker(&s.taker, &0, &10);
+    assert_eq!(escrows.len(), 2);
+    assert_eq!(escrows.get(0).unwrap().owner, s.owner);
+    assert_eq!(escrows.get(0).unwrap().status, EscrowStatus::Completed);
+    assert_eq!(escrows.get(1).unwrap().owner, other_owner);
+    assert_eq!(escrows.get(1).unwrap().status, EscrowStatus::Active);
+    assert_eq!(s.client.list_escrows_by_owner(&other_owner, &0, &10).len(), 1);
+}
+
+#[test]
+fn test_list_escrows_pages_across_chunks() {
+    let s = setup();
+    let total = INDEX_CHUNK_SIZE + 5;
+    StellarAssetClient::new(&s.env, &s.token.address).mint(&s.owner, &(AMOUNT * total as i128));
+    StellarAssetClient::new(&s.env, &s.native.address)
+        .mint(&s.owner, &(SAFETY_DEPOSIT * total as i128));
+    for i in 0..total {
+        create_escrow(&s, &std::format!("order_{:03}", i));
+    }
+
+    // Page size is capped, and pages spanning a chunk boundary stay in order
+    let capped = s.client.list_escrows_by_owner(&s.owner, &0, &u32::MAX);
+    assert_eq!(capped.len(), MAX_PAGE_SIZE);
+
+    let boundary = s.client.list_escrows_by_owner(&s.owner, &(INDEX_CHUNK_SIZE - 2), &4);
+    assert_eq!(boundary.len(), 4);
+    for (offset, escrow) in boundary.iter().enumerate() {
+        let i = INDEX_CHUNK_SIZE - 2 + offset as u32;
+        assert_eq!(escrow, s.client.list_escrows_by_owner(&s.owner, &i, &1).get(0).unwrap());
+    }
+    assert_eq!(
+        boundary.get(3).unwrap().order_id,
+        String::from_str(&s.env, "order_051")
+    );
+
+    let tail = s.client.list_escrows_by_taker(&s.taker, &INDEX_CHUNK_SIZE, &10);
+    assert_eq!(tail.len(), 5);
+}
+
+#[test]
+fn test_list_escrows_extends_index_ttl() {
+    let s = setup();
+    create_escrow(&s, "test_order_001");
+    let index_ttl = |key: &DataKey| s.env.as_contract(&s.client.address, || s.env.storage().persistent().get_ttl(key));
+    let owner_keys = [DataKey::OwnerCount(s.owner.clone()), DataKey::OwnerIndex(s.owner.clone(), 0)];
+    let taker_keys = [DataKey::TakerCount(s.taker.clone()), DataKey::TakerIndex(s.taker.clone(), 0)];
+    for key in owner_keys.iter().chain(taker_keys.iter()) {
+        assert_eq!(index_ttl(key), INDEX_BUMP_AMOUNT);
+    }
+
+    // Reading an index tops its entries back up, whatever the escrows' own TTLs
+    s.env.ledger().with_mut(|li| li.sequence_number += 2 * DAY_IN_LEDGERS);
+    s.client.list_escrows_by_owner(&s.owner, &0, &10);
+    for key in owner_keys.iter() {
+        assert_eq!(index_ttl(key), INDEX_BUMP_AMOUNT);
+    }
+    for key in taker_keys.iter() {
+        assert_eq!(index_ttl(key), INDEX_BUMP_AMOUNT - 2 * DAY_IN_LEDGERS);
+    }
+
+    s.client.list_escrows_by_taker(&s.taker, &0, &10);
+    for key in taker_keys.iter() {
+        assert_eq!(index_ttl(key), INDEX_BUMP_AMOUNT);
+    }
+}
+
+#[test]
+fn test_relayer_reveals_with_taker_signature() {
+    let s = setup();
+    let signer = register_reveal_key(&s);
+    assert_eq!(
+        s.client.get_reveal_key(&s.taker),
+        Some(BytesN::from_array(&s.env, &signer.verifying_key().to_bytes()))
+    );
+    let order_id = create_escrow(&s, "test_order_001");
+    let signature = sign_reveal(&s, &signer, &order_id, 0);
+
+    s.client.reveal_secret_signed(&order_id, &s.owner, &secret(&s.env), &0, &signature);
+
+    // Nobody's authorization was needed, and the funds still went to the taker
+    assert!(s.env.auths().is_empty());
+    assert_eq!(s.token.balance(&s.taker), AMOUNT);
+    assert_eq!(s.client.get_secret(&order_id, &s.owner), secret(&s.env));
+    assert_eq!(s.client.get_reveal_nonce(&s.taker), 1);
+}
+
+#[test]
+fn test_set_reveal_key_requires_taker_auth() {
+    let s = setup();
+    register_reveal_key(&s);
+
+    let (address, invocation) = s.env.auths().pop().unwrap();
+    assert_eq!(address, s.taker);
+    match invocation.function {
+        AuthorizedFunction::Contract((contract, name, _)) => {
+            assert_eq!(contract, s.client.address);
+            assert_eq!(name, Symbol::new(&s.env, "set_reveal_key"));
+        }
+        _ => panic!("expected a contract invocation"),
+    }
+}
+
+#[test]
+fn test_signed_reveal_without_reveal_key() {
+    let s = setup();
+    let order_id = create_escrow(&s, "test_order_001");
+    let signature = BytesN::from_array(&s.env, &[0; 64]);
+
+    assert_eq!(
+        s.client.try_reveal_secret_signed(&order_id, &s.owner, &secret(&s.env), &0, &signature),
+        Err(Ok(Error::NoRevealKey))
+    );
+}
+
+#[test]
+fn test_signed_reveal_wrong_nonce() {
+    let s = setup();
+    let signer = register_reveal_key(&s);
+    let first = create_escrow(&s, "test_order_001");
+    let second = create_escrow(&s, "test_order_002");
+
+    let signature = sign_reveal(&s, &signer, &first, 0);
+    s.client.reveal_secret_signed(&first, &s.owner, &secret(&s.env), &0, &signature);
+
+    // A signature over a used nonce can't be replayed on another escrow
+    let stale = sign_reveal(&s, &signer, &second, 0);
+    assert_eq!(
+        s.client.try_reveal_secret_signed(&second, &s.owner, &secret(&s.env), &0, &stale),
+        Err(Ok(Error::InvalidNonce))
+    );
+    let signature = sign_reveal(&s, &signer, &second, 1);
+    s.client.reveal_secret_signed(&second, &s.owner, &secret(&s.env), &1, &signature);
+    assert_eq!(s.token.balance(&s.taker), AMOUNT * 2);
+}
+
+#[test]
+fn test_signed_reveal_bad_signature() {
+    let s = setup();
+    register_reveal_key(&s);
+    let order_id = create_escrow(&s, "test_order_001");
+
+    // Signed by a key other than the one the taker registered
+    let impostor = SigningKey::from_bytes(&[8; 32]);
+    let signature = sign_reveal(&s, &impostor, &order_id, 0);
+
+    assert!(s
+        .client
+        .try_reveal_secret_signed(&order_id, &s.owner, &secret(&s.env), &0, &signature)
+        .is_err());
+    assert!(s.client.is_escrow_active(&order_id, &s.owner));
+    assert_eq!(s.client.get_reveal_nonce(&s.taker), 0);
+}
+
+#[test]
+fn test_signature_bound_to_escrow() {
+    let s = setup();
+    let signer = register_reveal_key(&s);
+    let first = create_escrow(&s, "test_order_001");
+    let second = create_escrow(&s, "test_order_002");
+
+    // A signature for one escrow doesn't unlock another at the same nonce
+    let signature = sign_reveal(&s, &signer, &first, 0);
+    assert!(s
+        .client
+        .try_reveal_secret_signed(&second, &s.owner, &secret(&s.env), &0, &signature)
+        .is_err());
+    assert!(s.client.is_escrow_active(&second, &s.owner));
+}
+
+#[test]
+fn test_safety_deposit_locked_at_creation() {
+    let s = setup();
+    create_escrow(&s, "test_order_001");
+
+    assert_eq!(s.native.balance(&s.client.address), SAFETY_DEPOSIT);
+    assert_eq!(s.native.balance(&s.owner), SAFETY_DEPOSIT * 99);
+}
+
+#[test]
+fn test_taker_reveal_collects_safety_deposit() {
+    let s = setup();
+    let order_id = create_escrow(&s, "test_order_001");
+
+    s.client.reveal_secret(&s.taker, &order_id, &s.owner, &secret(&s.env));
+
+    assert_eq!(s.native.balance(&s.taker), SAFETY_DEPOSIT * 101);
+    assert_eq!(s.native.balance(&s.client.address), 0);
+}
+
+#[test]
+fn test_public_withdrawal_window() {
+    let s = setup();
+    let order_id = create_escrow(&s, "test_order_001");
+    let keeper = Address::generate(&s.env);
+
+    s.env.ledger().set_timestamp(TIMELOCK / 2 - 1);
+    assert_eq!(
+        s.client.try_reveal_secret(&keeper, &order_id, &s.owner, &secret(&s.env)),
+        Err(Ok(Error::NotAuthorized))
+    );
+
+    // Once the public window opens, a keeper can finish the swap for the taker
+    s.env.ledger().set_timestamp(TIMELOCK / 2);
+    s.client.reveal_secret(&keeper, &order_id, &s.owner, &secret(&s.env));
+
+    assert_eq!(s.token.balance(&s.taker), AMOUNT);
+    assert_eq!(s.token.balance(&keeper), 0);
+    assert_eq!(s.native.balance(&keeper), SAFETY_DEPOSIT);
+}
+
+#[test]
+fn test_public_cancellation_window() {
+    let s = setup();
+    let order_id = create_escrow(&s, "test_order_001");
+    let keeper = Address::generate(&s.env);
+
+    s.env.ledger().set_timestamp(TIMELOCK + PUBLIC_CANCELLATION_DELAY - 1);
+    assert_eq!(
+        s.client.try_cancel_escrow(&keeper, &order_id, &s.owner),
+        Err(Ok(Error::NotAuthorized))
+    );
+
+    // Once the public window opens, a keeper can clean up the abandoned swap
+    s.env.ledger().set_timestamp(TIMELOCK + PUBLIC_CANCELLATION_DELAY);
+    s.client.cancel_escrow(&keeper, &order_id, &s.owner);
+
+    assert_eq!(s.token.balance(&s.owner), AMOUNT * 10);
+    assert_eq!(s.native.balance(&keeper), SAFETY_DEPOSIT);
+    assert_eq!(s.native.balance(&s.owner), SAFETY_DEPOSIT * 99);
+}
+
+#[test]
+fn test_owner_cancel_reclaims_safety_deposit() {
+    let s = setup();
+    let order_id = create_escrow(&s, "test_order_001");
+    s.env.ledger().set_timestamp(TIMELOCK);
+
+    s.client.cancel_escrow(&s.owner, &order_id, &s.owner);
+
+    assert_eq!(s.native.balance(&s.owner), SAFETY_DEPOSIT * 100);
+}
+
+#[test]
+fn test_reveal_before_withdrawal_window() {
+    let s = setup();
+    let order_id = String::from_str(&s.env, "test_order_001");
+    let hash = s.client.compute_hash(&secret(&s.env), &HashAlgo::Sha256);
+    let timelocks = Timelocks {
+        withdrawal: 60,
+        ..timelocks(TIMELOCK)
+    };
+    s.client.create_escrow(
+        &s.owner,
+        &order_id,
+        &hash,
+        &HashAlgo::Sha256,
+        &s.taker,
+        &s.token.address,
+        &AMOUNT,
+        &timelocks,
+        &SAFETY_DEPOSIT,
+        &0,
+    );
+
+    assert_eq!(
+        s.client.try_reveal_secret(&s.taker, &order_id, &s.owner, &secret(&s.env)),
+        Err(Ok(Error::WithdrawalNotStarted))
+    );
+    s.env.ledger().set_timestamp(60);
+    s.client.reveal_secret(&s.taker, &order_id, &s.owner, &secret(&s.env));
+}
+
+#[test]
+fn test_create_escrow_windows_out_of_order() {
+    let s = setup();
+    let hash = s.client.compute_hash(&secret(&s.env), &HashAlgo::Sha256);
+    let order_id = String::from_str(&s.env, "test_order_001");
+    let out_of_order = [
+        Timelocks { withdrawal: TIMELOCK, ..timelocks(TIMELOCK) },
+        Timelocks { public_withdrawal: TIMELOCK + 1, ..timelocks(TIMELOCK) },
+        Timelocks { public_cancellation: TIMELOCK - 1, ..timelocks(TIMELOCK) },
+    ];
+
+    for timelocks in out_of_order {
+        assert_eq!(
+            s.client.try_create_escrow(
+                &s.owner,
+                &order_id,
+                &hash,
+                &HashAlgo::Sha256,
+                &s.taker,
+                &s.token.address,
+                &AMOUNT,
+                &timelocks,
+                &SAFETY_DEPOSIT,
+                &0,
+            ),
+            Err(Ok(Error::InvalidTimelock))
+        );
+    }
+}
+
+#[test]
+fn test_create_escrow_negative_safety_deposit() {
+    let s = setup();
+    let hash = s.client.compute_hash(&secret(&s.env), &HashAlgo::Sha256);
+    let order_id = String::from_str(&s.env, "test_order_001");
+
+    assert_eq!(
+        s.client.try_create_escrow(
+            &s.owner,
+            &order_id,
+            &hash,
+            &HashAlgo::Sha256,
+            &s.taker,
+            &s.token.address,
+            &AMOUNT,
+            &timelocks(TIMELOCK),
+            &-1,
+            &0,
+        ),
+        Err(Ok(Error::InvalidAmount))
+    );
+}
+
+#[test]
+fn test_safety_deposit_requires_initialization() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, AtomicSwapEscrowContract);
+    let client = AtomicSwapEscrowContractClient::new(&env, &contract_id);
+    let token_id = env.register_stellar_asset_contract_v2(Address::generate(&env)).address();
+    let owner = Address::generate(&env);
+    let taker = Address::generate(&env);
+    StellarAssetClient::new(&env, &token_id).mint(&owner, &(AMOUNT * 2));
+    let hash = client.compute_hash(&secret(&env), &HashAlgo::Sha256);
+
+    assert_eq!(
+        client.try_create_escrow(
+            &owner,
+            &String::from_str(&env, "with_deposit"),
+            &hash,
+            &HashAlgo::Sha256,
+            &taker,
+            &token_id,
+            &AMOUNT,
+            &timelocks(TIMELOCK),
+            &SAFETY_DEPOSIT,
+            &0,
+        ),
+        Err(Ok(Error::NotInitialized))
+    );
+
+    // Escrows without a safety deposit don't need the native token
+    client.create_escrow(
+        &owner,
+        &String::from_str(&env, "no_deposit"),
+        &hash,
+        &HashAlgo::Sha256,
+        &taker,
+        &token_id,
+        &AMOUNT,
+        &timelocks(TIMELOCK),
+        &0,
+        &0,
+    );
+}
+
+// Smallest module the host accepts: just the env meta section declaring protocol 21
+const EMPTY_CONTRACT_WASM: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // magic, version
+    0x00, 0x1e, 0x11, // custom section, 30 bytes, 17-byte name
+    b'c', b'o', b'n', b't', b'r', b'a', b'c', b't', b'e', b'n', b'v', b'm', b'e', b't', b'a', b'v', b'0',
+    0x00, 0x00, 0x00, 0x00, // SC_ENV_META_KIND_INTERFACE_VERSION
+    0x00, 0x00, 0x00, 0x15, 0x00, 0x00, 0x00, 0x00, // protocol 21, pre-release 0
+];
+
+#[test]
+fn test_upgrade_replaces_contract_code() {
+    let s = setup();
+    let wasm_hash = s.env.deployer().upload_contract_wasm(EMPTY_CONTRACT_WASM);
+
+    s.client.upgrade(&wasm_hash);
+
+    let (address, invocation) = s.env.auths().pop().unwrap();
+    assert_eq!(address, s.admin);
+    assert!(matches!(
+        invocation.function,
+        AuthorizedFunction::Contract((_, name, _)) if name == Symbol::new(&s.env, "upgrade")
+    ));
+    // The new code exports nothing, so the old entry points are gone
+    assert!(s.client.try_get_current_timestamp().is_err());
+}
+
+#[test]
+fn test_upgrade_requires_admin() {
+    let s = setup();
+    let wasm_hash = s.env.deployer().upload_contract_wasm(EMPTY_CONTRACT_WASM);
+    let stranger = Address::generate(&s.env);
+
+    let result = s
+        .client
+        .mock_auths(&[MockAuth {
+            address: &stranger,
+            invoke: &MockAuthInvoke {
+                contract: &s.client.address,
+                fn_name: "upgrade",
+                args: (wasm_hash.clone(),).into_val(&s.env),
+                sub_invokes: &[],
+            },
+        }])
+        .try_upgrade(&wasm_hash);
+
+    assert!(result.is_err());
+    assert_eq!(s.client.get_current_timestamp(), 0);
+}
+
+#[test]
+fn test_upgrade_not_initialized() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, AtomicSwapEscrowContract);
+    let client = AtomicSwapEscrowContractClient::new(&env, &contract_id);
+    let wasm_hash = env.deployer().upload_contract_wasm(EMPTY_CONTRACT_WASM);
+
+    assert_eq!(client.try_upgrade(&wasm_hash), Err(Ok(Error::NotInitialized)));
+}
+
+#[test]
+fn test_migrate_rewrites_escrows() {
+    let s = setup();
+    let first = create_escrow(&s, "test_order_001");
+    let second = create_escrow(&s, "test_order_002");
+    let before = s.client.get_escrow(&first, &s.owner);
+
+    // Unknown escrows are reported back
+    let missing = String::from_str(&s.env, "missing");
+    let failed = s.client.migrate(&vec![
+        &s.env,
+        (first.clone(), s.owner.clone()),
+        (second.clone(), s.owner.clone()),
+        (missing.clone(), s.owner.clone()),
+    ]);
+
+    let (address, _) = s.env.auths().pop().unwrap();
+    assert_eq!(address, s.admin);
+    assert_eq!(failed, vec![&s.env, (missing, s.owner.clone())]);
+    assert_eq!(s.client.get_escrow(&first, &s.owner), before);
+}
+
+#[test]
+fn test_migrate_reports_undecodable_escrow() {
+    let s = setup();
+    let valid = create_escrow(&s, "test_order_001");
+    let corrupt = String::from_str(&s.env, "corrupt_order");
+    let key = DataKey::Escrow(corrupt.clone(), s.owner.clone());
+    s.env.as_contract(&s.client.address, || {
+        let partial: Map<Symbol, Val> = map![&s.env, (Symbol::new(&s.env, "order_id"), corrupt.to_val())];
+        s.env.storage().persistent().set(&key, &partial);
+    });
+
+    // The rest of the batch is still rewritten
+    let failed = s.client.migrate(&vec![
+        &s.env,
+        (corrupt.clone(), s.owner.clone()),
+        (valid.clone(), s.owner.clone()),
+    ]);
+
+    assert_eq!(failed, vec![&s.env, (corrupt, s.owner.clone())]);
+    assert_eq!(s.client.get_escrow(&valid, &s.owner).status, EscrowStatus::Active);
+}
+
+#[test]
+fn test_finish_migration_records_version() {
+    let s = setup();
+    s.env.as_contract(&s.client.address, || {
+        s.env.storage().instance().set(&DataKey::Version, &1u32);
+    });
+
+    // Migrating escrows alone doesn't change the recorded version
+    s.client.migrate(&Vec::new(&s.env));
+    assert_eq!(s.client.version(), 1);
+
+    s.client.finish_migration();
+
+    let (address, _) = s.env.auths().pop().unwrap();
+    assert_eq!(address, s.admin);
+    assert_eq!(s.client.version(), CONTRACT_VERSION);
+}
+
+// A contract deployed before versioning, upgraded to this code, reports the layout it was
+// written in until the admin finishes migrating
+#[test]
+fn test_version_after_upgrade_from_unversioned_deployment() {
+    let s = setup();
+    s.env.as_contract(&s.client.address, || {
+        s.env.storage().instance().remove(&DataKey::Version);
+    });
+
+    // Registering this code again at the address stands in for upgrading to its Wasm
+    let wasm_hash = s.env.deployer().upload_contract_wasm(EMPTY_CONTRACT_WASM);
+    s.client.upgrade(&wasm_hash);
+    s.env.register_contract(Some(&s.client.address), AtomicSwapEscrowContract);
+
+    assert_eq!(s.client.version(), 1);
+    assert_eq!(s.client.get_admin(), s.admin);
+
+    s.client.finish_migration();
+    assert_eq!(s.client.version(), CONTRACT_VERSION);
+}
+
+fn create_keccak_escrow(s: &Setup, order_id: &str, hash: &BytesN<32>) -> String {
+    let order_id = String::from_str(&s.env, order_id);
+    s.client.create_escrow(
+        &s.owner,
+        &order_id,
+        hash,
+        &HashAlgo::Keccak256,
+        &s.taker,
+        &s.token.address,
+        &AMOUNT,
+        &timelocks(TIMELOCK),
+        &SAFETY_DEPOSIT,
+        &0,
+    );
+    order_id
+}
+
+#[test]
+fn test_compute_hash_algorithms() {
+    let s = setup();
+    let data = Bytes::from_slice(&s.env, b"abc");
+
+    // Published test vectors for "abc"
+    let sha256 = [
+        0xba, 0x78, 0x16, 0xbf, 0x8f, 0x01, 0xcf, 0xea,
+        0x41, 0x41, 0x40, 0xde, 0x5d, 0xae, 0x22, 0x23,
+        0xb0, 0x03, 0x61, 0xa3, 0x96, 0x17, 0x7a, 0x9c,
+        0xb4, 0x10, 0xff, 0x61, 0xf2, 0x00, 0x15, 0xad,
+    ];
+    let keccak256 = [
+        0x4e, 0x03, 0x65, 0x7a, 0xea, 0x45, 0xa9, 0x4f,
+        0xc7, 0xd4, 0x7b, 0xa8, 0x26, 0xc8, 0xd6, 0x67,
+        0xc0, 0xd1, 0xe6, 0xe3, 0x3a, 0x64, 0xa0, 0x36,
+        0xec, 0x44, 0xf5, 0x8f, 0xa1, 0x2d, 0x6c, 0x45,
+    ];
+    assert_eq!(
+        s.client.compute_hash(&data, &HashAlgo::Sha256),
+        BytesN::from_array(&s.env, &sha256)
+    );
+    assert_eq!(
+        s.client.compute_hash(&data, &HashAlgo::Keccak256),
+        BytesN::from_array(&s.env, &keccak256)
+    );
+}
+
+#[test]
+fn test_keccak_escrow_reveal() {
+    let s = setup();
+    let hash = s.client.compute_hash(&secret(&s.env), &HashAlgo::Keccak256);
+    let order_id = create_keccak_escrow(&s, "test_order_001", &hash);
+    assert_eq!(
+        s.client.get_escrow(&order_id, &s.owner).hash_algo,
+        HashAlgo::Keccak256
+    );
+
+    s.client.reveal_secret(&s.taker, &order_id, &s.owner, &secret(&s.env));
+
+    // Watchers on the keccak chain can look the secret up by the same hash
+    assert_eq!(s.client.get_secret_by_hash(&hash), secret(&s.env));
+    assert_eq!(s.token.balance(&s.taker), AMOUNT);
+}
+
+#[test]
+fn test_hash_algo_mismatch() {
+    let s = setup();
+    let sha256_hash = s.client.compute_hash(&secret(&s.env), &HashAlgo::Sha256);
+    let order_id = create_keccak_escrow(&s, "test_order_001", &sha256_hash);
+
+    assert_eq!(
+        s.client.try_reveal_secret(&s.taker, &order_id, &s.owner, &secret(&s.env)),
+        Err(Ok(Error::HashMismatch))
+    );
+}
+
+#[test]
+fn test_migrate_v1_escrow_defaults_to_sha256() {
+    let s = setup();
+    let order_id = String::from_str(&s.env, "legacy_order");
+    let key = DataKey::Escrow(order_id.clone(), s.owner.clone());
+    let legacy = EscrowV1 {
+        order_id: order_id.clone(),
+        hash: s.client.compute_hash(&secret(&s.env), &HashAlgo::Sha256),
+        owner: s.owner.clone(),
+        taker: s.taker.clone(),
+        token_contract: s.token.address.clone(),
+        amount: AMOUNT,
+        safety_deposit: 0,
+        withdrawal_at: 0,
+        public_withdrawal_at: TIMELOCK / 2,
+        timelock: TIMELOCK,
+        public_cancellation_at: TIMELOCK + PUBLIC_CANCELLATION_DELAY,
+        status: EscrowStatus::Active,
+        created_at: 0,
+        secret: None,
+    };
+    s.env.as_contract(&s.client.address, || {
+        s.env.storage().persistent().set(&key, &legacy);
+        s.env.storage().instance().set(&DataKey::Version, &1u32);
+    });
+    StellarAssetClient::new(&s.env, &s.token.address).mint(&s.client.address, &AMOUNT);
+    assert_eq!(s.client.version(), 1);
+
+    assert!(s.client.migrate(&vec![&s.env, (order_id.clone(), s.owner.clone())]).is_empty());
+    s.client.finish_migration();
+
+    assert_eq!(s.client.version(), CONTRACT_VERSION);
+    let escrow = s.client.get_escrow(&order_id, &s.owner);
+    assert_eq!(escrow.hash_algo, HashAlgo::Sha256);
+    assert_eq!(escrow.hash, legacy.hash);
+    assert_eq!(escrow.timelock, legacy.timelock);
+
+    s.client.reveal_secret(&s.taker, &order_id, &s.owner, &secret(&s.env));
+    assert_eq!(s.token.balance(&s.taker), AMOUNT);
+}
+
+const PARTS: u32 = 3;
+
+fn part_secret(env: &Env, index: u32) -> Bytes {
+    Bytes::from_array(env, &[index as u8 + 1; 8])
+}
+
+// Sorted-pair SHA-256 Merkle tree over H(index || H(secret)) leaves; an odd node is carried up
+fn merkle_tree(env: &Env, parts: u32) -> (BytesN<32>, std::vec::Vec<Vec<BytesN<32>>>) {
+    let mut level: std::vec::Vec<BytesN<32>> = (0..parts)
+        .map(|i| {
+            let mut leaf = Bytes::from_array(env, &i.to_be_bytes());
+            leaf.append(&env.crypto().sha256(&part_secret(env, i)).into());
+            env.crypto().sha256(&leaf).into()
+        })
+        .collect();
+    let mut positions: std::vec::Vec<usize> = (0..parts as usize).collect();
+    let mut proofs: std::vec::Vec<Vec<BytesN<32>>> = (0..parts).map(|_| Vec::new(env)).collect();
+    while level.len() > 1 {
+        for (proof, position) in proofs.iter_mut().zip(positions.iter_mut()) {
+            let sibling = *position ^ 1;
+            if sibling < level.len() {
+                proof.push_back(level[sibling].clone());
+            }
+            *position /= 2;
+        }
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => {
+                    let (left, right) = if left < right { (left, right) } else { (right, left) };
+                    let mut node: Bytes = left.clone().into();
+                    node.append(&right.clone().into());
+                    env.crypto().sha256(&node).into()
+                }
+                [single] => single.clone(),
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+    (level[0].clone(), proofs)
+}
+
+fn create_partial_escrow(s: &Setup, order_id: &str) -> (String, std::vec::Vec<Vec<BytesN<32>>>) {
+    let order_id = String::from_str(&s.env, order_id);
+    let (root, proofs) = merkle_tree(&s.env, PARTS);
+    s.client.create_escrow(
+        &s.owner,
+        &order_id,
+        &root,
+        &HashAlgo::Sha256,
+        &s.taker,
+        &s.token.address,
+        &AMOUNT,
+        &timelocks(TIMELOCK),
+        &SAFETY_DEPOSIT,
+        &PARTS,
+    );
+    (order_id, proofs)
+}
+
+#[test]
+fn test_partial_fills_release_tranches() {
+    let s = setup();
+    let (order_id, proofs) = create_partial_escrow(&s, "test_order_001");
+
+    s.client.reveal_partial(&s.taker, &order_id, &s.owner, &part_secret(&s.env, 0), &0, &proofs[0]);
+    s.client.reveal_partial(&s.taker, &order_id, &s.owner, &part_secret(&s.env, 2), &2, &proofs[2]);
+
+    // Tranches round down except the last, so 1000 splits as 333 / 333 / 334
+    let escrow = s.client.get_escrow(&order_id, &s.owner);
+    assert_eq!(escrow.status, EscrowStatus::Active);
+    assert_eq!(escrow.filled, 333 + 334);
+    assert_eq!(escrow.filled_parts, 0b101);
+    assert_eq!(s.token.balance(&s.taker), 333 + 334);
+    assert_eq!(s.native.balance(&s.taker), SAFETY_DEPOSIT * 100 + 3 + 4);
+
+    s.client.reveal_partial(&s.taker, &order_id, &s.owner, &part_secret(&s.env, 1), &1, &proofs[1]);
+
+    let escrow = s.client.get_escrow(&order_id, &s.owner);
+    assert_eq!(escrow.status, EscrowStatus::Completed);
+    assert_eq!(escrow.filled, AMOUNT);
+    assert_eq!(s.token.balance(&s.taker), AMOUNT);
+    assert_eq!(s.native.balance(&s.taker), SAFETY_DEPOSIT * 101);
+    assert_eq!(s.native.balance(&s.client.address), 0);
+
+    // Each part's secret is published under its own hash
+    let hash = s.client.compute_hash(&part_secret(&s.env, 1), &HashAlgo::Sha256);
+    assert_eq!(s.client.get_secret_by_hash(&hash), part_secret(&s.env, 1));
+}
+
+#[test]
+fn test_partial_fill_reused_index() {
+    let s = setup();
+    let (order_id, proofs) = create_partial_escrow(&s, "test_order_001");
+    s.client.reveal_partial(&s.taker, &order_id, &s.owner, &part_secret(&s.env, 0), &0, &proofs[0]);
+
+    assert_eq!(
+        s.client.try_reveal_partial(&s.taker, &order_id, &s.owner, &part_secret(&s.env, 0), &0, &proofs[0]),
+        Err(Ok(Error::PartAlreadyFilled))
+    );
+    assert_eq!(s.token.balance(&s.taker), 333);
+}
+
+#[test]
+fn test_partial_fill_invalid_proof() {
+    let s = setup();
+    let (order_id, proofs) = create_partial_escrow(&s, "test_order_001");
+
+    // Wrong secret, and a valid secret claimed under another index
+    assert_eq!(
+        s.client.try_reveal_partial(&s.taker, &order_id, &s.owner, &secret(&s.env), &0, &proofs[0]),
+        Err(Ok(Error::InvalidProof))
+    );
+    assert_eq!(
+        s.client.try_reveal_partial(&s.taker, &order_id, &s.owner, &part_secret(&s.env, 0), &1, &proofs[0]),
+        Err(Ok(Error::InvalidProof))
+    );
+    assert_eq!(
+        s.client.try_reveal_partial(&s.taker, &order_id, &s.owner, &part_secret(&s.env, 0), &PARTS, &proofs[0]),
+        Err(Ok(Error::InvalidPartIndex))
+    );
+    assert_eq!(s.client.get_escrow(&order_id, &s.owner).filled, 0);
+}
+
+#[test]
+fn test_partial_fill_mode_is_per_escrow() {
+    let s = setup();
+    let single = create_escrow(&s, "test_order_001");
+    let (partial, _) = create_partial_escrow(&s, "test_order_002");
+
+    assert_eq!(
+        s.client.try_reveal_partial(&s.taker, &single, &s.owner, &secret(&s.env), &0, &Vec::new(&s.env)),
+        Err(Ok(Error::PartialFillsDisabled))
+    );
+    assert_eq!(
+        s.client.try_reveal_secret(&s.taker, &partial, &s.owner, &part_secret(&s.env, 0)),
+        Err(Ok(Error::PartialFillRequired))
+    );
+}
+
+#[test]
+fn test_reveal_secret_rejects_merkle_root_preimage() {
+    let s = setup();
+    let order_id = String::from_str(&s.env, "test_order_001");
+    let (root, proofs) = merkle_tree(&s.env, 2);
+    s.client.create_escrow(
+        &s.owner,
+        &order_id,
+        &root,
+        &HashAlgo::Sha256,
+        &s.taker,
+        &s.token.address,
+        &AMOUNT,
+        &timelocks(TIMELOCK),
+        &SAFETY_DEPOSIT,
+        &2,
+    );
+
+    // With two parts each leaf's proof is the other leaf, and the sorted leaves hash to the root
+    let (leaf0, leaf1) = (proofs[1].get(0).unwrap(), proofs[0].get(0).unwrap());
+    let (left, right) = if leaf0 < leaf1 { (leaf0, leaf1) } else { (leaf1, leaf0) };
+    let mut preimage: Bytes = left.into();
+    preimage.append(&right.into());
+    assert_eq!(s.client.compute_hash(&preimage, &HashAlgo::Sha256), root);
+
+    assert_eq!(
+        s.client.try_reveal_secret(&s.taker, &order_id, &s.owner, &preimage),
+        Err(Ok(Error::PartialFillRequired))
+    );
+    assert_eq!(s.token.balance(&s.taker), 0);
+    assert_eq!(s.client.get_escrow(&order_id, &s.owner).filled, 0);
+}
+
+#[test]
+fn test_partial_fill_windows() {
+    let s = setup();
+    let (order_id, proofs) = create_partial_escrow(&s, "test_order_001");
+    let keeper = Address::generate(&s.env);
+
+    assert_eq!(
+        s.client.try_reveal_partial(&keeper, &order_id, &s.owner, &part_secret(&s.env, 0), &0, &proofs[0]),
+        Err(Ok(Error::NotAuthorized))
+    );
+
+    s.env.ledger().set_timestamp(TIMELOCK);
+    assert_eq!(
+        s.client.try_reveal_partial(&s.taker, &order_id, &s.owner, &part_secret(&s.env, 0), &0, &proofs[0]),
+        Err(Ok(Error::TimelockExpired))
+    );
+}
+
+#[test]
+fn test_cancel_after_partial_fill() {
+    let s = setup();
+    let (order_id, proofs) = create_partial_escrow(&s, "test_order_001");
+    s.client.reveal_partial(&s.taker, &order_id, &s.owner, &part_secret(&s.env, 0), &0, &proofs[0]);
+
+    s.env.ledger().set_timestamp(TIMELOCK);
+    s.client.cancel_escrow(&s.owner, &order_id, &s.owner);
+
+    // Only the unfilled amount and the unpaid part of the deposit come back
+    assert_eq!(s.token.balance(&s.owner), AMOUNT * 10 - 333);
+    assert_eq!(s.native.balance(&s.owner), SAFETY_DEPOSIT * 100 - 3);
+    assert_eq!(s.token.balance(&s.client.address), 0);
+    assert_eq!(s.native.balance(&s.client.address), 0);
+
+    let event = s.env.events().all().last().unwrap();
+    let event = EscrowCancelledEvent::try_from_val(&s.env, &event.2).unwrap();
+    assert_eq!(event.amount, AMOUNT - 333);
+}
+
+#[test]
+fn test_create_escrow_parts_bounds() {
+    let s = setup();
+    let (root, _) = merkle_tree(&s.env, PARTS);
+    let order_id = String::from_str(&s.env, "test_order_001");
+    let create = |amount: i128, parts: u32| {
+        s.client.try_create_escrow(
+            &s.owner,
+            &order_id,
+            &root,
+            &HashAlgo::Sha256,
+            &s.taker,
+            &s.token.address,
+            &amount,
+            &timelocks(TIMELOCK),
+            &SAFETY_DEPOSIT,
+            &parts,
+        )
+    };
+
+    assert_eq!(create(AMOUNT, MAX_PARTS + 1), Err(Ok(Error::TooManyParts)));
+    assert_eq!(create(2, PARTS), Err(Ok(Error::InvalidAmount)));
+    assert!(create(AMOUNT, MAX_PARTS).is_ok());
+}
+
+#[test]
+fn test_migrate_v2_escrow_to_single_secret() {
+    let s = setup();
+    let order_id = String::from_str(&s.env, "legacy_order");
+    let key = DataKey::Escrow(order_id.clone(), s.owner.clone());
+    let legacy = EscrowV2 {
+        order_id: order_id.clone(),
+        hash: s.client.compute_hash(&secret(&s.env), &HashAlgo::Keccak256),
+        hash_algo: HashAlgo::Keccak256,
+        owner: s.owner.clone(),
+        taker: s.taker.clone(),
+        token_contract: s.token.address.clone(),
+        amount: AMOUNT,
+        safety_deposit: 0,
+        withdrawal_at: 0,
+        public_withdrawal_at: TIMELOCK / 2,
+        timelock: TIMELOCK,
+        public_cancellation_at: TIMELOCK + PUBLIC_CANCELLATION_DELAY,
+        status: EscrowStatus::Active,
+        created_at: 0,
+        secret: None,
+    };
+    s.env.as_contract(&s.client.address, || {
+        s.env.storage().persistent().set(&key, &legacy);
+        s.env.storage().instance().set(&DataKey::Version, &2u32);
+    });
+    StellarAssetClient::new(&s.env, &s.token.address).mint(&s.client.address, &AMOUNT);
+
+    assert!(s.client.migrate(&vec![&s.env, (order_id.clone(), s.owner.clone())]).is_empty());
+    s.client.finish_migration();
+
+    assert_eq!(s.client.version(), CONTRACT_VERSION);
+    let escrow = s.client.get_escrow(&order_id, &s.owner);
+    assert_eq!(escrow.hash_algo, HashAlgo::Keccak256);
+    assert_eq!((escrow.parts, escrow.filled, escrow.filled_parts), (0, 0, 0));
+
+    s.client.reveal_secret(&s.taker, &order_id, &s.owner, &secret(&s.env));
+    assert_eq!(s.client.get_escrow(&order_id, &s.owner).filled, AMOUNT);
+}
+
+// Per-transaction network limits the budget and footprint are measured against
+const TX_CPU_INSN_LIMIT: u64 = 100_000_000;
+const TX_MEM_BYTES_LIMIT: u64 = 40 * 1024 * 1024;
+const TX_READ_ENTRIES_LIMIT: usize = 40;
+const TX_WRITE_ENTRIES_LIMIT: usize = 25;
+
+// Runs `invoke` as its own transaction would: with a fresh budget and an empty recorded
+// footprint. Asserts both stay within the transaction limits and returns the footprint's
+// (read, written) entry counts. Native test execution only meters host calls, so the Wasm
+// build leaves less CPU and memory headroom; the footprint is the same.
+fn assert_fits_transaction(s: &Setup, invoke: impl FnOnce()) -> (usize, usize) {
+    let host = s.env.host();
+    host.with_mut_storage(|storage| {
+        storage.footprint = Footprint::default();
+        Ok(())
+    }).unwrap();
+    s.env.budget().reset_default();
+    invoke();
+
+    assert!(s.env.budget().cpu_instruction_cost() < TX_CPU_INSN_LIMIT);
+    assert!(s.env.budget().memory_bytes_cost() < TX_MEM_BYTES_LIMIT);
+
+    // Every footprint entry counts as a read, the read-write ones also as writes
+    let footprint = host.with_mut_storage(|storage| Ok(storage.footprint.clone())).unwrap();
+    let reads = footprint.0.len();
+    let writes = footprint.0.iter(&host.budget_cloned()).unwrap()
+        .filter(|(_, access)| *access == AccessType::ReadWrite)
+        .count();
+    assert!(reads <= TX_READ_ENTRIES_LIMIT);
+    assert!(writes <= TX_WRITE_ENTRIES_LIMIT);
+    (reads, writes)
+}
+
+fn reveal_args(s: &Setup, order_id: &String, secret: Bytes) -> RevealArgs {
+    RevealArgs {
+        order_id: order_id.clone(),
+        owner: s.owner.clone(),
+        secret,
+    }
+}
+
+#[test]
+fn test_reveal_many_reports_each_order() {
+    let s = setup();
+    let first = create_escrow(&s, "test_order_001");
+    let second = create_escrow(&s, "test_order_002");
+    let missing = String::from_str(&s.env, "missing");
+
+    let results = s.client.reveal_many(
+        &s.taker,
+        &vec![
+            &s.env,
+            reveal_args(&s, &first, secret(&s.env)),
+            reveal_args(&s, &second, Bytes::from_slice(&s.env, &[9])),
+            reveal_args(&s, &missing, secret(&s.env)),
+        ],
+    );
+
+    assert_eq!(
+        results,
+        vec![
+            &s.env,
+            BatchResult::Done,
+            BatchResult::Failed(Error::HashMismatch as u32),
+            BatchResult::Failed(Error::EscrowNotFound as u32),
+        ]
+    );
+    assert_eq!(s.token.balance(&s.taker), AMOUNT);
+    assert!(!s.client.is_escrow_active(&first, &s.owner));
+    assert!(s.client.is_escrow_active(&second, &s.owner));
+}
+
+#[test]
+fn test_cancel_many_reports_each_order() {
+    let s = setup();
+    let first = create_escrow(&s, "test_order_001");
+    let second = create_escrow(&s, "test_order_002");
+    let completed = create_escrow(&s, "test_order_003");
+    s.client.reveal_secret(&s.taker, &completed, &s.owner, &secret(&s.env));
+    s.env.ledger().set_timestamp(TIMELOCK);
+
+    let results = s.client.cancel_many(
+        &s.owner,
+        &vec![
+            &s.env,
+            (first, s.owner.clone()),
+            (second, s.owner.clone()),
+            (completed, s.owner.clone()),
+        ],
+    );
+
+    assert_eq!(
+        results,
+        vec![
+            &s.env,
+            BatchResult::Done,
+            BatchResult::Done,
+            BatchResult::Failed(Error::EscrowNotActive as u32),
+        ]
+    );
+    assert_eq!(s.token.balance(&s.owner), AMOUNT * 9);
+    assert_eq!(s.native.balance(&s.owner), SAFETY_DEPOSIT * 99);
+}
+
+#[test]
+fn test_batch_too_large() {
+    let s = setup();
+    let order_id = String::from_str(&s.env, "test_order_001");
+    let mut reveals = Vec::new(&s.env);
+    let mut escrows = Vec::new(&s.env);
+    for _ in 0..=MAX_BATCH_SIZE {
+        reveals.push_back(reveal_args(&s, &order_id, secret(&s.env)));
+        escrows.push_back((order_id.clone(), s.owner.clone()));
+    }
+
+    assert_eq!(
+        s.client.try_reveal_many(&s.taker, &reveals),
+        Err(Ok(Error::BatchTooLarge))
+    );
+    assert_eq!(
+        s.client.try_cancel_many(&s.owner, &escrows),
+        Err(Ok(Error::BatchTooLarge))
+    );
+}
+
+// A full batch of MAX_BATCH_SIZE orders stays within the transaction's CPU,
+// memory and ledger entry limits
+#[test]
+fn test_reveal_many_max_batch_fits_transaction() {
+    let s = setup();
+    let keeper = Address::generate(&s.env);
+    let mut reveals = Vec::new(&s.env);
+    for i in 0..MAX_BATCH_SIZE {
+        // Distinct hashlocks, so every order also writes its own secret entry
+        let order_id = String::from_str(&s.env, &std::format!("test_order_{i:03}"));
+        s.client.create_escrow(
+            &s.owner,
+            &order_id,
+            &s.client.compute_hash(&part_secret(&s.env, i), &HashAlgo::Sha256),
+            &HashAlgo::Sha256,
+            &s.taker,
+            &s.token.address,
+            &AMOUNT,
+            &timelocks(TIMELOCK),
+            &SAFETY_DEPOSIT,
+            &0,
+        );
+        reveals.push_back(reveal_args(&s, &order_id, part_secret(&s.env, i)));
+    }
+    s.env.ledger().set_timestamp(TIMELOCK / 2);
+
+    let (reads, writes) = assert_fits_transaction(&s, || {
+        let results = s.client.reveal_many(&keeper, &reveals);
+        assert!(results.iter().all(|result| result == BatchResult::Done));
+    });
+
+    // One authorization covers the whole batch
+    assert_eq!(s.env.auths().len(), 1);
+    assert_eq!(s.token.balance(&s.taker), AMOUNT * MAX_BATCH_SIZE as i128);
+
+    // Each order writes its escrow and secret; the payouts share the contract's and the
+    // payee's token balances, and the keeper's authorization writes its nonce
+    assert_eq!(writes, 2 * MAX_BATCH_SIZE as usize + 5);
+    assert!(reads > writes);
+}
+
+// Escrow for `owner` and `taker` in a token of its own, so no balance entry is shared with
+// other orders
+fn create_isolated_escrow(s: &Setup, order_id: &String, owner: &Address, taker: &Address, secret: &Bytes) {
+    let token_id = s.env.register_stellar_asset_contract_v2(Address::generate(&s.env)).address();
+    StellarAssetClient::new(&s.env, &token_id).mint(owner, &AMOUNT);
+    StellarAssetClient::new(&s.env, &s.native.address).mint(owner, &SAFETY_DEPOSIT);
+    s.client.create_escrow(
+        owner,
+        order_id,
+        &s.client.compute_hash(secret, &HashAlgo::Sha256),
+        &HashAlgo::Sha256,
+        taker,
+        &token_id,
+        &AMOUNT,
+        &timelocks(TIMELOCK),
+        &SAFETY_DEPOSIT,
+        &0,
+    );
+}
+
+// The worst case MAX_BATCH_SIZE is derived from: every order in its own token, paying its
+// own taker
+#[test]
+fn test_reveal_many_mixed_max_batch_fits_transaction() {
+    let s = setup();
+    let keeper = Address::generate(&s.env);
+    let mut reveals = Vec::new(&s.env);
+    for i in 0..MAX_BATCH_SIZE {
+        let order_id = String::from_str(&s.env, &std::format!("test_order_{i:03}"));
+        create_isolated_escrow(&s, &order_id, &s.owner, &Address::generate(&s.env), &part_secret(&s.env, i));
+        reveals.push_back(reveal_args(&s, &order_id, part_secret(&s.env, i)));
+    }
+    s.env.ledger().set_timestamp(TIMELOCK / 2);
+
+    let (_, writes) = assert_fits_transaction(&s, || {
+        let results = s.client.reveal_many(&keeper, &reveals);
+        assert!(results.iter().all(|result| result == BatchResult::Done));
+    });
+
+    // Each order writes its escrow, its secret and two balances of its token, plus the
+    // contract's and keeper's native balances and the keeper's nonce. One more order wouldn't fit.
+    assert_eq!(writes, 4 * MAX_BATCH_SIZE as usize + 3);
+    assert!(writes + 4 > TX_WRITE_ENTRIES_LIMIT);
+}
+
+#[test]
+fn test_cancel_many_mixed_max_batch_fits_transaction() {
+    let s = setup();
+    let keeper = Address::generate(&s.env);
+    let mut escrows = Vec::new(&s.env);
+    for i in 0..MAX_BATCH_SIZE {
+        let order_id = String::from_str(&s.env, &std::format!("test_order_{i:03}"));
+        let owner = Address::generate(&s.env);
+        create_isolated_escrow(&s, &order_id, &owner, &s.taker, &secret(&s.env));
+        escrows.push_back((order_id, owner));
+    }
+    s.env.ledger().set_timestamp(TIMELOCK + PUBLIC_CANCELLATION_DELAY);
+
+    let (_, writes) = assert_fits_transaction(&s, || {
+        let results = s.client.cancel_many(&keeper, &escrows);
+        assert!(results.iter().all(|result| result == BatchResult::Done));
+    });
+
+    // Each order writes its escrow and two balances of its token, plus the contract's and
+    // keeper's native balances and the keeper's nonce
+    assert_eq!(writes, 3 * MAX_BATCH_SIZE as usize + 3);
+}
+
+#[test]
+fn test_cancel_many_max_batch_fits_transaction() {
+    let s = setup();
+    let keeper = Address::generate(&s.env);
+    let mut escrows = Vec::new(&s.env);
+    for i in 0..MAX_BATCH_SIZE {
+        let order_id = create_escrow(&s, &std::format!("test_order_{i:03}"));
+        escrows.push_back((order_id, s.owner.clone()));
+    }
+    s.env.ledger().set_timestamp(TIMELOCK + PUBLIC_CANCELLATION_DELAY);
+
+    let (_, writes) = assert_fits_transaction(&s, || {
+        let results = s.client.cancel_many(&keeper, &escrows);
+        assert!(results.iter().all(|result| result == BatchResult::Done));
+    });
+    assert_eq!(s.token.balance(&s.owner), AMOUNT * 10);
+
+    // Each order writes its escrow; the refunds share four token balances, plus the keeper's nonce
+    assert_eq!(writes, MAX_BATCH_SIZE as usize + 5);
+}
+
+// Escrow events this contract published, in order
+fn escrow_events(s: &Setup) -> std::vec::Vec<(Vec<Val>, Val)> {
+    s.env.events().all().iter()
+        .filter(|(contract, topics, _)| {
+            *contract == s.client.address
+                && topics.first().map(|topic| Symbol::try_from_val(&s.env, &topic))
+                    == Some(Ok(symbol_short!("escrow")))
+        })
+        .map(|(_, topics, data)| (topics, data))
+        .collect()
+}
+
+#[test]
+fn test_created_event_topics() {
+    let s = setup();
+    let order_id = create_escrow(&s, "test_order_001");
+    let hash = s.client.compute_hash(&secret(&s.env), &HashAlgo::Sha256);
+
+    let events = escrow_events(&s);
+    assert_eq!(events.len(), 1);
+    let (topics, data) = &events[0];
+    assert_eq!(
+        *topics,
+        (symbol_short!("escrow"), symbol_short!("created"), hash.clone(), s.owner.clone()).into_val(&s.env)
+    );
+    let event = EscrowCreatedEvent::try_from_val(&s.env, data).unwrap();
+    assert_eq!(event.order_id, order_id);
+    assert_eq!(event.hash, hash);
+    assert_eq!(event.hash_algo, HashAlgo::Sha256);
+    assert_eq!(event.taker, s.taker);
+    assert_eq!(event.amount, AMOUNT);
+}
+
+#[test]
+fn test_completed_event_topics() {
+    let s = setup();
+    let order_id = create_escrow(&s, "test_order_001");
+    let hash = s.client.compute_hash(&secret(&s.env), &HashAlgo::Sha256);
+
+    s.client.reveal_secret(&s.taker, &order_id, &s.owner, &secret(&s.env));
+
+    let (contract, topics, _) = s.env.events().all().last().unwrap();
+    assert_eq!(contract, s.client.address);
+    assert_eq!(
+        topics,
+        (symbol_short!("escrow"), symbol_short!("completed"), hash, s.taker.clone()).into_val(&s.env)
+    );
+}
+
+#[test]
+fn test_cancelled_event_topics() {
+    let s = setup();
+    let order_id = create_escrow(&s, "test_order_001");
+    let hash = s.client.compute_hash(&secret(&s.env), &HashAlgo::Sha256);
+    s.env.ledger().set_timestamp(TIMELOCK);
+
+    s.client.cancel_escrow(&s.owner, &order_id, &s.owner);
+
+    let (contract, topics, _) = s.env.events().all().last().unwrap();
+    assert_eq!(contract, s.client.address);
+    assert_eq!(
+        topics,
+        (symbol_short!("escrow"), symbol_short!("cancelled"), hash, s.owner.clone()).into_val(&s.env)
+    );
+}
+
+#[test]
+fn test_filled_event_topics() {
+    let s = setup();
+    let (order_id, proofs) = create_partial_escrow(&s, "test_order_001");
+    let (root, _) = merkle_tree(&s.env, PARTS);
+
+    s.client.reveal_partial(&s.taker, &order_id, &s.owner, &part_secret(&s.env, 1), &1, &proofs[1]);
+
+    let (_, topics, data) = s.env.events().all().last().unwrap();
+    assert_eq!(
+        topics,
+        (symbol_short!("escrow"), symbol_short!("filled"), root, s.taker.clone()).into_val(&s.env)
+    );
+    let event = EscrowPartFilledEvent::try_from_val(&s.env, &data).unwrap();
+    assert_eq!(event.index, 1);
+    assert_eq!(event.secret, part_secret(&s.env, 1));
+}
+
+#[test]
+fn test_watcher_finds_secret_by_hash_topic() {
+    let s = setup();
+    let first = create_escrow(&s, "test_order_001");
+    let other_secret = Bytes::from_slice(&s.env, &[6, 7, 8]);
+    let other_hash = s.client.compute_hash(&other_secret, &HashAlgo::Keccak256);
+    let second = create_keccak_escrow(&s, "test_order_002", &other_hash);
+
+    s.client.reveal_secret(&s.taker, &first, &s.owner, &secret(&s.env));
+    s.client.reveal_secret(&s.taker, &second, &s.owner, &other_secret);
+
+    // A watcher on the counter-leg subscribes to ("escrow", *, hash, *)
+    let matching: std::vec::Vec<_> = escrow_events(&s)
+        .into_iter()
+        .filter(|(topics, _)| {
+            topics.get(2).map(|topic| BytesN::<32>::try_from_val(&s.env, &topic))
+                == Some(Ok(other_hash.clone()))
+        })
+        .collect();
+    // It sees the escrow created, then completed
+    assert_eq!(matching.len(), 2);
+    let created = EscrowCreatedEvent::try_from_val(&s.env, &matching[0].1).unwrap();
+    assert_eq!(created.order_id, second);
+    let event = EscrowCompletedEvent::try_from_val(&s.env, &matching[1].1).unwrap();
+    assert_eq!(event.order_id, second);
+    assert_eq!(event.secret, other_secret);
+}
@@ -0,0 +1,297 @@
+#![no_std]
+use soroban_sdk::{
+    contract, contractimpl, contracttype, contracterror, log, symbol_short, token, Address, Bytes, BytesN, Env, String
+};
+
+// Ledgers close roughly every 5 seconds
+const LEDGER_SECONDS: u64 = 5;
+const DAY_IN_LEDGERS: u32 = 17_280;
+
+// The escrow stays live this long past its last window so it can still be finalized
+const ESCROW_TTL_MARGIN: u32 = 7 * DAY_IN_LEDGERS;
+
+// Bounds on the cancellation and public cancellation offsets, in seconds
+const MIN_TIMELOCK_DURATION: u64 = 5 * 60;
+const MAX_TIMELOCK_DURATION: u64 = 30 * 24 * 60 * 60;
+
+// Status enum for escrow lifecycle
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EscrowStatus {
+    Active,
+    Completed,
+    Cancelled,
+}
+
+// Hash function the hashlock was built with
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HashAlgo {
+    Sha256,
+    Keccak256,
+}
+
+// Offsets from creation, in seconds, at which each finalization window opens
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Timelocks {
+    pub withdrawal: u64,          // taker can reveal
+    pub public_withdrawal: u64,   // anyone can reveal on the taker's behalf
+    pub cancellation: u64,        // reveals close, owner can cancel
+    pub public_cancellation: u64, // anyone can cancel on the owner's behalf
+}
+
+// Order description the factory initializes the escrow with
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EscrowParams {
+    pub order_id: String,
+    pub hash: BytesN<32>,
+    pub hash_algo: HashAlgo,
+    pub owner: Address,
+    pub taker: Address,
+    pub token_contract: Address,
+    pub amount: i128,
+    pub timelocks: Timelocks,
+}
+
+// Escrow data, the only entry in this contract's instance storage
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Escrow {
+    pub order_id: String,
+    pub hash: BytesN<32>,
+    pub hash_algo: HashAlgo,
+    pub owner: Address,
+    pub taker: Address,
+    pub token_contract: Address,
+    pub amount: i128,
+    pub withdrawal_at: u64,
+    pub public_withdrawal_at: u64,
+    pub timelock: u64,               // cancellation window start
+    pub public_cancellation_at: u64,
+    pub status: EscrowStatus,
+    pub created_at: u64,
+    pub secret: Option<Bytes>, // set once the taker reveals it
+}
+
+// Storage keys for instance storage
+#[contracttype]
+pub enum DataKey {
+    Escrow,
+}
+
+// Custom error types
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum Error {
+    NotAuthorized = 1,
+    NotInitialized = 2,
+    AlreadyInitialized = 3,
+    HashMismatch = 4,
+    TimelockNotExpired = 5,
+    TimelockExpired = 6,
+    InvalidAmount = 7,
+    InvalidHash = 8,
+    InvalidTimelock = 9,
+    EscrowNotActive = 10,
+    WithdrawalNotStarted = 11,
+    SecretNotRevealed = 12,
+}
+
+// Event types for logging, published under ("escrow", <action>, hash, <party paid>) topics
+#[contracttype]
+pub struct EscrowCompletedEvent {
+    pub order_id: String,
+    pub owner: Address,
+    pub taker: Address,
+    pub amount: i128,
+    pub secret: Bytes,
+}
+
+#[contracttype]
+pub struct EscrowCancelledEvent {
+    pub order_id: String,
+    pub owner: Address,
+    pub amount: i128,
+}
+
+#[contract]
+pub struct OrderEscrowContract;
+
+#[contractimpl]
+impl OrderEscrowContract {
+    /// Records the order this instance escrows. Called once by the factory in the same
+    /// transaction that deploys the instance, before it transfers `amount` in.
+    pub fn init(env: Env, params: EscrowParams) -> Result<(), Error> {
+        if env.storage().instance().has(&DataKey::Escrow) {
+            return Err(Error::AlreadyInitialized);
+        }
+
+        // Validate amount, hashlock and timelocks
+        if params.amount <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+        if params.hash == BytesN::from_array(&env, &[0; 32]) {
+            return Err(Error::InvalidHash);
+        }
+        let timelocks = params.timelocks;
+        let windows_in_order = timelocks.withdrawal <= timelocks.public_withdrawal
+            && timelocks.withdrawal < timelocks.cancellation
+            && timelocks.public_withdrawal <= timelocks.cancellation
+            && timelocks.cancellation <= timelocks.public_cancellation;
+        if !windows_in_order
+            || timelocks.cancellation < MIN_TIMELOCK_DURATION
+            || timelocks.public_cancellation > MAX_TIMELOCK_DURATION
+        {
+            return Err(Error::InvalidTimelock);
+        }
+
+        let current_time = env.ledger().timestamp();
+        let public_cancellation_at = current_time
+            .checked_add(timelocks.public_cancellation)
+            .ok_or(Error::InvalidTimelock)?;
+        let escrow = Escrow {
+            order_id: params.order_id,
+            hash: params.hash,
+            hash_algo: params.hash_algo,
+            owner: params.owner,
+            taker: params.taker,
+            token_contract: params.token_contract,
+            amount: params.amount,
+            withdrawal_at: current_time + timelocks.withdrawal,
+            public_withdrawal_at: current_time + timelocks.public_withdrawal,
+            timelock: current_time + timelocks.cancellation,
+            public_cancellation_at,
+            status: EscrowStatus::Active,
+            created_at: current_time,
+            secret: None,
+        };
+        env.storage().instance().set(&DataKey::Escrow, &escrow);
+        Self::extend_escrow_ttl(&env, &escrow);
+        Ok(())
+    }
+
+    /// Reveals the secret, releasing the escrowed tokens to the taker.
+    pub fn withdraw(env: Env, caller: Address, secret: Bytes) -> Result<(), Error> {
+        caller.require_auth();
+        let mut escrow = Self::get_escrow(env.clone())?;
+        if escrow.status != EscrowStatus::Active {
+            return Err(Error::EscrowNotActive);
+        }
+
+        // Only the taker can reveal, until the public withdrawal window opens
+        let current_time = env.ledger().timestamp();
+        if caller != escrow.taker && current_time < escrow.public_withdrawal_at {
+            return Err(Error::NotAuthorized);
+        }
+        if current_time < escrow.withdrawal_at {
+            return Err(Error::WithdrawalNotStarted);
+        }
+        if current_time >= escrow.timelock {
+            return Err(Error::TimelockExpired);
+        }
+
+        let computed_hash: BytesN<32> = match escrow.hash_algo {
+            HashAlgo::Sha256 => env.crypto().sha256(&secret).into(),
+            HashAlgo::Keccak256 => env.crypto().keccak256(&secret).into(),
+        };
+        if computed_hash != escrow.hash {
+            return Err(Error::HashMismatch);
+        }
+
+        token::Client::new(&env, &escrow.token_contract).transfer(
+            &env.current_contract_address(),
+            &escrow.taker,
+            &escrow.amount,
+        );
+
+        escrow.status = EscrowStatus::Completed;
+        escrow.secret = Some(secret.clone());
+        env.storage().instance().set(&DataKey::Escrow, &escrow);
+        Self::extend_escrow_ttl(&env, &escrow);
+
+        let event = EscrowCompletedEvent {
+            order_id: escrow.order_id.clone(),
+            owner: escrow.owner,
+            taker: escrow.taker.clone(),
+            amount: escrow.amount,
+            secret,
+        };
+        env.events().publish(
+            (symbol_short!("escrow"), symbol_short!("completed"), escrow.hash, escrow.taker),
+            event,
+        );
+
+        log!(&env, "Escrow completed: order_id={}, amount={}", escrow.order_id, escrow.amount);
+        Ok(())
+    }
+
+    /// Cancels the escrow after timelock expiry, returning the tokens to the owner.
+    pub fn cancel(env: Env, caller: Address) -> Result<(), Error> {
+        caller.require_auth();
+        let mut escrow = Self::get_escrow(env.clone())?;
+        if escrow.status != EscrowStatus::Active {
+            return Err(Error::EscrowNotActive);
+        }
+
+        // Only the owner can cancel, until the public cancellation window opens
+        let current_time = env.ledger().timestamp();
+        if caller != escrow.owner && current_time < escrow.public_cancellation_at {
+            return Err(Error::NotAuthorized);
+        }
+        if current_time < escrow.timelock {
+            return Err(Error::TimelockNotExpired);
+        }
+
+        token::Client::new(&env, &escrow.token_contract).transfer(
+            &env.current_contract_address(),
+            &escrow.owner,
+            &escrow.amount,
+        );
+
+        escrow.status = EscrowStatus::Cancelled;
+        env.storage().instance().set(&DataKey::Escrow, &escrow);
+        Self::extend_escrow_ttl(&env, &escrow);
+
+        let event = EscrowCancelledEvent {
+            order_id: escrow.order_id.clone(),
+            owner: escrow.owner.clone(),
+            amount: escrow.amount,
+        };
+        env.events().publish(
+            (symbol_short!("escrow"), symbol_short!("cancelled"), escrow.hash, escrow.owner),
+            event,
+        );
+
+        log!(&env, "Escrow cancelled: order_id={}, amount={}", escrow.order_id, escrow.amount);
+        Ok(())
+    }
+
+    /// Gets the escrow details.
+    pub fn get_escrow(env: Env) -> Result<Escrow, Error> {
+        env.storage().instance()
+            .get(&DataKey::Escrow)
+            .ok_or(Error::NotInitialized)
+    }
+
+    /// Returns the secret revealed to complete the escrow, for the counter-leg.
+    pub fn get_secret(env: Env) -> Result<Bytes, Error> {
+        Self::get_escrow(env)?.secret.ok_or(Error::SecretNotRevealed)
+    }
+
+    /// Keeps the instance (and its code) live until past the last window plus a margin,
+    /// capped at the network's maximum TTL
+    fn extend_escrow_ttl(env: &Env, escrow: &Escrow) {
+        let ledgers_left = escrow.public_cancellation_at.saturating_sub(env.ledger().timestamp()) / LEDGER_SECONDS;
+        let ttl = u32::try_from(ledgers_left)
+            .unwrap_or(u32::MAX)
+            .saturating_add(ESCROW_TTL_MARGIN)
+            .min(env.storage().max_ttl());
+        env.storage().instance().extend_ttl(ttl, ttl);
+    }
+}
+
+#[cfg(test)]
+mod test;
\ No newline at end of file
@@ -0,0 +1,207 @@
+#![cfg(test)]
+
+use super::*;
+use soroban_sdk::testutils::storage::Instance as _;
+use soroban_sdk::testutils::{Address as _, Events, Ledger};
+use soroban_sdk::token::{StellarAssetClient, TokenClient};
+use soroban_sdk::{Env, IntoVal};
+
+const AMOUNT: i128 = 1000;
+const TIMELOCK: u64 = 3600;
+const PUBLIC_CANCELLATION_DELAY: u64 = 600;
+
+struct Setup<'a> {
+    env: Env,
+    client: OrderEscrowContractClient<'a>,
+    token: TokenClient<'a>,
+    owner: Address,
+    taker: Address,
+}
+
+fn secret(env: &Env) -> Bytes {
+    Bytes::from_slice(env, &[1, 2, 3, 4, 5])
+}
+
+fn params(env: &Env, token: &Address, owner: &Address, taker: &Address) -> EscrowParams {
+    EscrowParams {
+        order_id: String::from_str(env, "test_order_001"),
+        hash: env.crypto().sha256(&secret(env)).into(),
+        hash_algo: HashAlgo::Sha256,
+        owner: owner.clone(),
+        taker: taker.clone(),
+        token_contract: token.clone(),
+        amount: AMOUNT,
+        timelocks: Timelocks {
+            withdrawal: 0,
+            public_withdrawal: TIMELOCK / 2,
+            cancellation: TIMELOCK,
+            public_cancellation: TIMELOCK + PUBLIC_CANCELLATION_DELAY,
+        },
+    }
+}
+
+// Initializes and funds the escrow the way the factory does
+fn setup<'a>() -> Setup<'a> {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register_contract(None, OrderEscrowContract);
+    let client = OrderEscrowContractClient::new(&env, &contract_id);
+    let token_id = env.register_stellar_asset_contract_v2(Address::generate(&env)).address();
+    let token = TokenClient::new(&env, &token_id);
+    let owner = Address::generate(&env);
+    let taker = Address::generate(&env);
+
+    client.init(&params(&env, &token_id, &owner, &taker));
+    StellarAssetClient::new(&env, &token_id).mint(&contract_id, &AMOUNT);
+
+    Setup {
+        env,
+        client,
+        token,
+        owner,
+        taker,
+    }
+}
+
+#[test]
+fn test_init_records_escrow() {
+    let s = setup();
+    let escrow = s.client.get_escrow();
+
+    assert_eq!(escrow.owner, s.owner);
+    assert_eq!(escrow.taker, s.taker);
+    assert_eq!(escrow.amount, AMOUNT);
+    assert_eq!(escrow.timelock, TIMELOCK);
+    assert_eq!(escrow.public_cancellation_at, TIMELOCK + PUBLIC_CANCELLATION_DELAY);
+    assert_eq!(escrow.status, EscrowStatus::Active);
+
+    // The instance outlives the last window by the margin
+    let ttl = s.env.as_contract(&s.client.address, || s.env.storage().instance().get_ttl());
+    assert!(ttl >= ((TIMELOCK + PUBLIC_CANCELLATION_DELAY) / LEDGER_SECONDS) as u32 + ESCROW_TTL_MARGIN);
+}
+
+#[test]
+fn test_init_twice() {
+    let s = setup();
+    let params = params(&s.env, &s.token.address, &s.owner, &s.taker);
+
+    assert_eq!(s.client.try_init(&params), Err(Ok(Error::AlreadyInitialized)));
+}
+
+#[test]
+fn test_init_validation() {
+    let env = Env::default();
+    let client = OrderEscrowContractClient::new(&env, &env.register_contract(None, OrderEscrowContract));
+    let token = Address::generate(&env);
+    let valid = params(&env, &token, &Address::generate(&env), &Address::generate(&env));
+
+    let mut invalid = valid.clone();
+    invalid.amount = 0;
+    assert_eq!(client.try_init(&invalid), Err(Ok(Error::InvalidAmount)));
+
+    let mut invalid = valid.clone();
+    invalid.hash = BytesN::from_array(&env, &[0; 32]);
+    assert_eq!(client.try_init(&invalid), Err(Ok(Error::InvalidHash)));
+
+    let mut invalid = valid.clone();
+    invalid.timelocks.cancellation = MIN_TIMELOCK_DURATION - 1;
+    assert_eq!(client.try_init(&invalid), Err(Ok(Error::InvalidTimelock)));
+
+    assert_eq!(client.try_get_escrow(), Err(Ok(Error::NotInitialized)));
+}
+
+#[test]
+fn test_withdraw_success() {
+    let s = setup();
+
+    s.client.withdraw(&s.taker, &secret(&s.env));
+
+    assert_eq!(s.token.balance(&s.taker), AMOUNT);
+    assert_eq!(s.token.balance(&s.client.address), 0);
+    assert_eq!(s.client.get_escrow().status, EscrowStatus::Completed);
+    assert_eq!(s.client.get_secret(), secret(&s.env));
+}
+
+#[test]
+fn test_withdraw_wrong_secret() {
+    let s = setup();
+
+    assert_eq!(
+        s.client.try_withdraw(&s.taker, &Bytes::from_slice(&s.env, &[9])),
+        Err(Ok(Error::HashMismatch))
+    );
+    assert_eq!(s.client.try_get_secret(), Err(Ok(Error::SecretNotRevealed)));
+}
+
+#[test]
+fn test_withdraw_windows() {
+    let s = setup();
+    let keeper = Address::generate(&s.env);
+
+    assert_eq!(
+        s.client.try_withdraw(&keeper, &secret(&s.env)),
+        Err(Ok(Error::NotAuthorized))
+    );
+
+    s.env.ledger().set_timestamp(TIMELOCK);
+    assert_eq!(
+        s.client.try_withdraw(&s.taker, &secret(&s.env)),
+        Err(Ok(Error::TimelockExpired))
+    );
+}
+
+#[test]
+fn test_public_withdrawal() {
+    let s = setup();
+    let keeper = Address::generate(&s.env);
+    s.env.ledger().set_timestamp(TIMELOCK / 2);
+
+    s.client.withdraw(&keeper, &secret(&s.env));
+
+    assert_eq!(s.token.balance(&s.taker), AMOUNT);
+    assert_eq!(s.token.balance(&keeper), 0);
+}
+
+#[test]
+fn test_cancel_success() {
+    let s = setup();
+
+    assert_eq!(s.client.try_cancel(&s.owner), Err(Ok(Error::TimelockNotExpired)));
+
+    s.env.ledger().set_timestamp(TIMELOCK);
+    s.client.cancel(&s.owner);
+
+    assert_eq!(s.token.balance(&s.owner), AMOUNT);
+    assert_eq!(s.client.get_escrow().status, EscrowStatus::Cancelled);
+    assert_eq!(s.client.try_withdraw(&s.taker, &secret(&s.env)), Err(Ok(Error::EscrowNotActive)));
+}
+
+#[test]
+fn test_public_cancellation() {
+    let s = setup();
+    let keeper = Address::generate(&s.env);
+
+    s.env.ledger().set_timestamp(TIMELOCK);
+    assert_eq!(s.client.try_cancel(&keeper), Err(Ok(Error::NotAuthorized)));
+
+    s.env.ledger().set_timestamp(TIMELOCK + PUBLIC_CANCELLATION_DELAY);
+    s.client.cancel(&keeper);
+
+    assert_eq!(s.token.balance(&s.owner), AMOUNT);
+}
+
+#[test]
+fn test_event_topics() {
+    let s = setup();
+    let hash = s.client.get_escrow().hash;
+
+    s.client.withdraw(&s.taker, &secret(&s.env));
+
+    let (contract, topics, _) = s.env.events().all().last().unwrap();
+    assert_eq!(contract, s.client.address);
+    assert_eq!(
+        topics,
+        (symbol_short!("escrow"), symbol_short!("completed"), hash, s.taker.clone()).into_val(&s.env)
+    );
+}
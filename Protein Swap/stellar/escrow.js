@@ -34,10 +34,19 @@ const CONFIG = {
   orderId: process.env.ORDER_ID || generateOrderId(),
   // Secret (will be generated if not provided)
   secret: process.env.SECRET || generateSecret(),
+  // Hash function the hashlock is built with: Sha256 or Keccak256
+  hashAlgo: process.env.HASH_ALGO || 'Sha256',
   // Amount in tokens
   amount: parseInt(process.env.AMOUNT) || 100,
-  // Timelock duration in seconds
-  timelockDuration: parseInt(process.env.TIMELOCK_DURATION) || 3600
+  // Offsets from creation, in seconds, at which each finalization window opens
+  timelocks: {
+    withdrawal: parseInt(process.env.WITHDRAWAL_DELAY) || 0,
+    publicWithdrawal: parseInt(process.env.PUBLIC_WITHDRAWAL_DELAY) || 1800,
+    cancellation: parseInt(process.env.TIMELOCK_DURATION) || 3600,
+    publicCancellation: parseInt(process.env.PUBLIC_CANCELLATION_DELAY) || 4200
+  },
+  // Safety deposit in native token stroops, paid to whoever finalizes the escrow
+  safetyDeposit: parseInt(process.env.SAFETY_DEPOSIT) || 0
 };
 
 // Validate required environment variables
@@ -80,10 +89,37 @@ console.log('Owner Address:', ownerKeypair.publicKey());
 console.log('Taker Address:', CONFIG.takerAddress);
 console.log('Order ID:', CONFIG.orderId);
 console.log('Secret:', CONFIG.secret);
+console.log('Hash Algorithm:', CONFIG.hashAlgo);
 console.log('Amount:', CONFIG.amount, 'tokens');
-console.log('Timelock Duration:', CONFIG.timelockDuration, 'seconds');
+console.log('Timelocks:', CONFIG.timelocks);
+console.log('Safety Deposit:', CONFIG.safetyDeposit, 'stroops');
 console.log('');
 
+// HashAlgo enum as the contract expects it, a vector holding the variant name
+function hashAlgoToScVal(hashAlgo) {
+  return xdr.ScVal.scvVec([xdr.ScVal.scvSymbol(hashAlgo)]);
+}
+
+// Timelocks struct as the contract expects it, a map keyed by field name
+function timelocksToScVal(timelocks) {
+  return nativeToScVal(
+    {
+      cancellation: timelocks.cancellation,
+      public_cancellation: timelocks.publicCancellation,
+      public_withdrawal: timelocks.publicWithdrawal,
+      withdrawal: timelocks.withdrawal
+    },
+    {
+      type: {
+        cancellation: ['symbol', 'u64'],
+        public_cancellation: ['symbol', 'u64'],
+        public_withdrawal: ['symbol', 'u64'],
+        withdrawal: ['symbol', 'u64']
+      }
+    }
+  );
+}
+
 // Helper function to invoke contract with actual transaction submission
 async function invokeContractTransaction(contractId, functionName, args, sourceKeypair) {
   try {
@@ -211,16 +247,24 @@ async function readContract(contractId, functionName, args, sourceKeypair) {
 async function createEscrow() {
   console.log('🔒 Creating escrow with specified parameters...\n');
   
-  // Compute hash of secret
+  // Compute hash of secret. Node has no keccak256, so the contract's compute_hash is used for it.
   const secretBuffer = Buffer.from(CONFIG.secret);
-  const hash = crypto.createHash('sha256').update(secretBuffer).digest();
+  const hash = CONFIG.hashAlgo === 'Keccak256'
+    ? Buffer.from(await readContract(
+        CONFIG.contractId,
+        'compute_hash',
+        [nativeToScVal(secretBuffer, { type: 'bytes' }), hashAlgoToScVal(CONFIG.hashAlgo)],
+        ownerKeypair
+      ))
+    : crypto.createHash('sha256').update(secretBuffer).digest();
   
   console.log('🎯 Escrow Parameters:');
   console.log('- Order ID:', CONFIG.orderId);
   console.log('- Secret:', CONFIG.secret);
-  console.log('- Hash:', hash.toString('hex'));
+  console.log('- Hash:', hash.toString('hex'), `(${CONFIG.hashAlgo})`);
   console.log('- Amount:', CONFIG.amount, 'tokens');
-  console.log('- Timelock Duration:', CONFIG.timelockDuration, 'seconds');
+  console.log('- Timelocks:', CONFIG.timelocks);
+  console.log('- Safety Deposit:', CONFIG.safetyDeposit, 'stroops');
   console.log('- Owner:', ownerKeypair.publicKey());
   console.log('- Taker:', CONFIG.takerAddress);
   console.log('');
@@ -233,10 +277,14 @@ async function createEscrow() {
         nativeToScVal(ownerKeypair.publicKey(), { type: 'address' }),
         nativeToScVal(CONFIG.orderId, { type: 'string' }),
         nativeToScVal(hash, { type: 'bytes' }),
+        hashAlgoToScVal(CONFIG.hashAlgo),
         nativeToScVal(CONFIG.takerAddress, { type: 'address' }),
         nativeToScVal(CONFIG.tokenContractId, { type: 'address' }),
         nativeToScVal(CONFIG.amount, { type: 'i128' }),
-        nativeToScVal(CONFIG.timelockDuration, { type: 'u64' })
+        timelocksToScVal(CONFIG.timelocks),
+        nativeToScVal(CONFIG.safetyDeposit, { type: 'i128' }),
+        // Single secret; partially fillable escrows take the Merkle tree's leaf count
+        nativeToScVal(0, { type: 'u32' })
       ],
       ownerKeypair
     );